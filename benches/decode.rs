@@ -0,0 +1,29 @@
+use std::hint::black_box;
+
+use b6502::{Cpu, parse_opcode};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+/// Every opcode followed by two operand bytes
+fn program() -> Vec<u8> {
+    (0..=255u8).flat_map(|op| [op, 0x34, 0x12]).collect()
+}
+
+fn decode_all(program: &[u8], cpu: Cpu) {
+    for instruction in program.chunks(3) {
+        let mut cursor = instruction.iter().copied();
+        let _ = black_box(parse_opcode(&mut cursor, cpu, true));
+    }
+}
+
+fn decode(c: &mut Criterion) {
+    let program = program();
+    c.bench_function("decode nmos", |b| {
+        b.iter(|| decode_all(black_box(&program), Cpu::Nmos))
+    });
+    c.bench_function("decode cmos", |b| {
+        b.iter(|| decode_all(black_box(&program), Cpu::Cmos))
+    });
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
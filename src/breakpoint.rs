@@ -0,0 +1,148 @@
+use std::fmt::Display;
+
+use crate::{Bus, Machine, expr::Expr};
+
+/// What happens when a breakpoint is hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakAction {
+    /// Stop before the instruction
+    Pause,
+    /// Report the hit and keep running
+    Log,
+}
+
+/// Stops before the instruction at `addr`, or anywhere, when the condition holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub condition: Option<Expr>,
+    pub action: BreakAction,
+    /// Hits left to pass over before acting
+    pub ignore: u64,
+    /// Times the address and condition matched, ignored hits included
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    pub fn new(addr: Option<u16>, condition: Option<Expr>) -> Self {
+        Breakpoint {
+            addr,
+            condition,
+            action: BreakAction::Pause,
+            ignore: 0,
+            hits: 0,
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "{addr:0>4x}")?,
+            None => write!(f, "*")?,
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {condition}")?;
+        }
+        if self.action == BreakAction::Log {
+            write!(f, " log")?;
+        }
+        write!(f, ", hit {} times", self.hits)?;
+        if self.ignore > 0 {
+            write!(f, ", ignore next {}", self.ignore)?;
+        }
+        Ok(())
+    }
+}
+
+/// A breakpoint acting on the machine state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakHit {
+    pub index: usize,
+    pub pc: u16,
+    pub action: BreakAction,
+}
+
+impl Display for BreakHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "breakpoint {} at {:0>4x}", self.index, self.pc)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    list: Vec<Option<Breakpoint>>,
+}
+
+impl Breakpoints {
+    /// Add a breakpoint, returns its index
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        self.list.push(Some(breakpoint));
+        self.list.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Breakpoint> {
+        self.list.get_mut(index).and_then(Option::take)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Breakpoint> {
+        self.list.get_mut(index).and_then(Option::as_mut)
+    }
+
+    /// The breakpoints still set, with their index
+    pub fn list(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.list
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.as_ref().map(|b| (i, b)))
+    }
+
+    /// The first pausing breakpoint matching the instruction about to run, leaving the
+    /// hit and ignore counts alone
+    pub fn pausing<B: Bus>(&self, machine: &Machine<B>) -> anyhow::Result<Option<usize>> {
+        let pc = machine.registers().pc;
+        for (index, breakpoint) in self.list() {
+            if breakpoint.action != BreakAction::Pause
+                || breakpoint.addr.is_some_and(|addr| addr != pc)
+            {
+                continue;
+            }
+            match &breakpoint.condition {
+                Some(condition) if !condition.holds(machine)? => {}
+                _ => return Ok(Some(index)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Count the breakpoints matching the instruction about to run, returns the
+    /// ones acting on it
+    pub fn check<B: Bus>(&mut self, machine: &Machine<B>) -> anyhow::Result<Vec<BreakHit>> {
+        let pc = machine.registers().pc;
+        let mut hits = Vec::new();
+        for (index, breakpoint) in self.list.iter_mut().enumerate() {
+            let Some(breakpoint) = breakpoint else {
+                continue;
+            };
+            if breakpoint.addr.is_some_and(|addr| addr != pc) {
+                continue;
+            }
+            if let Some(condition) = &breakpoint.condition
+                && !condition.holds(machine)?
+            {
+                continue;
+            }
+            breakpoint.hits += 1;
+            if breakpoint.ignore > 0 {
+                breakpoint.ignore -= 1;
+                continue;
+            }
+            hits.push(BreakHit {
+                index,
+                pc,
+                action: breakpoint.action,
+            });
+        }
+        Ok(hits)
+    }
+}
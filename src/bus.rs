@@ -0,0 +1,389 @@
+use std::{any::Any, ops::RangeInclusive};
+
+use anyhow::{Context, bail};
+use log::trace;
+use rand::Rng;
+
+pub const ADDRESS_SPACE: usize = 0x10000;
+
+/// The CPU view of the 16-bit address space
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+    /// Read without side effects (debugger, display)
+    fn peek(&self, addr: u16) -> u8;
+    /// Write bypassing write protection (loader)
+    fn poke(&mut self, addr: u16, value: u8) {
+        self.write(addr, value)
+    }
+    /// Level of the IRQ line, true when asserted
+    fn irq(&self) -> bool {
+        false
+    }
+    /// Level of the NMI line, true when asserted
+    fn nmi(&self) -> bool {
+        false
+    }
+    /// Everything needed to restore the memory and devices, for save states.
+    /// By default the address space as seen by `peek`.
+    fn save_state(&self) -> Vec<u8> {
+        (0..=0xFFFF).map(|addr| self.peek(addr)).collect()
+    }
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        if state.len() != ADDRESS_SPACE {
+            bail!(
+                "expected {ADDRESS_SPACE} bytes of memory, found {}",
+                state.len()
+            );
+        }
+        for (addr, &value) in state.iter().enumerate() {
+            self.poke(addr as u16, value);
+        }
+        Ok(())
+    }
+}
+
+/// A memory mapped device, addressed by the offset from the start of its region
+pub trait Device: Any {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.peek(offset)
+    }
+    fn write(&mut self, offset: u16, value: u8);
+    fn peek(&self, offset: u16) -> u8;
+    fn poke(&mut self, offset: u16, value: u8) {
+        self.write(offset, value)
+    }
+    fn irq(&self) -> bool {
+        false
+    }
+    fn nmi(&self) -> bool {
+        false
+    }
+    /// Device state for save states, stateless devices keep the default
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    fn load_state(&mut self, _state: &[u8]) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct Region {
+    range: RangeInclusive<u16>,
+    device: Box<dyn Device>,
+}
+
+/// A bus made of devices registered on address ranges.
+/// Regions mapped later take precedence over the earlier ones,
+/// unmapped addresses read as 0 and ignore writes.
+#[derive(Default)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        MemoryMap::default()
+    }
+
+    pub fn map(&mut self, range: RangeInclusive<u16>, device: impl Device) -> &mut Self {
+        trace!("[bus] map {:0>4x}..={:0>4x}", range.start(), range.end());
+        self.regions.push(Region {
+            range,
+            device: Box::new(device),
+        });
+        self
+    }
+
+    pub fn device<T: Device>(&self) -> Option<&T> {
+        self.regions
+            .iter()
+            .rev()
+            .find_map(|r| (r.device.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn device_mut<T: Device>(&mut self) -> Option<&mut T> {
+        self.regions
+            .iter_mut()
+            .rev()
+            .find_map(|r| (r.device.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+
+    fn region(&self, addr: u16) -> Option<usize> {
+        self.regions.iter().rposition(|r| r.range.contains(&addr))
+    }
+}
+
+impl Bus for MemoryMap {
+    fn read(&mut self, addr: u16) -> u8 {
+        match self.region(addr) {
+            Some(i) => {
+                let region = &mut self.regions[i];
+                region.device.read(addr - region.range.start())
+            }
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if let Some(i) = self.region(addr) {
+            let region = &mut self.regions[i];
+            region.device.write(addr - region.range.start(), value);
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match self.region(addr) {
+            Some(i) => {
+                let region = &self.regions[i];
+                region.device.peek(addr - region.range.start())
+            }
+            None => 0,
+        }
+    }
+
+    fn poke(&mut self, addr: u16, value: u8) {
+        if let Some(i) = self.region(addr) {
+            let region = &mut self.regions[i];
+            region.device.poke(addr - region.range.start(), value);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.regions.iter().any(|r| r.device.irq())
+    }
+
+    fn nmi(&self) -> bool {
+        self.regions.iter().any(|r| r.device.nmi())
+    }
+
+    /// The state of every region in mapping order, each prefixed by its length
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        for region in &self.regions {
+            let device = region.device.save_state();
+            state.extend_from_slice(&(device.len() as u32).to_le_bytes());
+            state.extend_from_slice(&device);
+        }
+        state
+    }
+
+    fn load_state(&mut self, mut state: &[u8]) -> anyhow::Result<()> {
+        let mut devices = Vec::with_capacity(self.regions.len());
+        while let Some((len, rest)) = state.split_first_chunk::<4>() {
+            let len = u32::from_le_bytes(*len) as usize;
+            devices.push(rest.get(..len).context("truncated device state")?);
+            state = &rest[len..];
+        }
+        if !state.is_empty() || devices.len() != self.regions.len() {
+            bail!(
+                "state for {} regions, {} are mapped",
+                devices.len(),
+                self.regions.len()
+            );
+        }
+        for (i, (region, device)) in self.regions.iter_mut().zip(devices).enumerate() {
+            region
+                .device
+                .load_state(device)
+                .with_context(|| format!("region {i}"))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Ram {
+    data: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        Ram {
+            data: vec![0; size],
+        }
+    }
+}
+
+impl Device for Ram {
+    fn write(&mut self, offset: u16, value: u8) {
+        if let Some(byte) = self.data.get_mut(offset as usize) {
+            *byte = value;
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.data.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        load_bytes(&mut self.data, state)
+    }
+}
+
+/// Read only memory, CPU writes are ignored but the loader can still poke
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>) -> Self {
+        Rom { data }
+    }
+}
+
+impl Device for Rom {
+    fn write(&mut self, offset: u16, value: u8) {
+        trace!(
+            "[rom] ignored write {:0>2x} at offset {:0>4x}",
+            value, offset
+        );
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.data.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn poke(&mut self, offset: u16, value: u8) {
+        if let Some(byte) = self.data.get_mut(offset as usize) {
+            *byte = value;
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        load_bytes(&mut self.data, state)
+    }
+}
+
+fn load_bytes(data: &mut [u8], state: &[u8]) -> anyhow::Result<()> {
+    if state.len() != data.len() {
+        bail!("expected {} bytes, found {}", data.len(), state.len());
+    }
+    data.copy_from_slice(state);
+    Ok(())
+}
+
+/// Yields a random number in 1..16 on every read
+#[derive(Default)]
+pub struct Random {
+    last: u8,
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: u16) -> u8 {
+        self.last = rand::rng().random_range(1..16);
+        self.last
+    }
+
+    fn write(&mut self, _offset: u16, _value: u8) {}
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.last
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.last]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        let mut last = [0];
+        load_bytes(&mut last, state)?;
+        self.last = last[0];
+        Ok(())
+    }
+}
+
+/// A single byte latch holding the last key pressed
+#[derive(Default)]
+pub struct Keyboard {
+    key: u8,
+}
+
+impl Keyboard {
+    pub fn press(&mut self, key: u8) {
+        self.key = key;
+    }
+}
+
+impl Device for Keyboard {
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.key = value;
+    }
+
+    fn peek(&self, _offset: u16) -> u8 {
+        self.key
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.key]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        let mut key = [0];
+        load_bytes(&mut key, state)?;
+        self.key = key[0];
+        Ok(())
+    }
+}
+
+pub const FRAME_WIDTH: usize = 32;
+pub const FRAME_HEIGHT: usize = 32;
+
+/// 32x32 pixels, one byte per pixel holding a color index
+pub struct Framebuffer {
+    pixels: [u8; FRAME_WIDTH * FRAME_HEIGHT],
+    dirty: bool,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer {
+            pixels: [0; FRAME_WIDTH * FRAME_HEIGHT],
+            dirty: true,
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns whether the frame changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+impl Device for Framebuffer {
+    fn write(&mut self, offset: u16, value: u8) {
+        if let Some(pixel) = self.pixels.get_mut(offset as usize)
+            && *pixel != value
+        {
+            trace!("[display] display buffer dirty");
+            *pixel = value;
+            self.dirty = true;
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        self.pixels.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.pixels.to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        load_bytes(&mut self.pixels, state)?;
+        self.dirty = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+
+use log::warn;
+
+/// Frames kept at most, a balanced program can't nest deeper in 256 bytes of stack
+const MAX_FRAMES: usize = 128;
+/// Divergences kept until taken, later ones are dropped
+const MAX_DIVERGENCES: usize = 256;
+
+/// How a frame was entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Jsr,
+    Brk,
+    Irq,
+    Nmi,
+}
+
+impl Display for FrameKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            FrameKind::Jsr => "jsr",
+            FrameKind::Brk => "brk",
+            FrameKind::Irq => "irq",
+            FrameKind::Nmi => "nmi",
+        })
+    }
+}
+
+/// A subroutine call or an interrupt that hasn't returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// The JSR or BRK instruction, or the instruction an interrupt came before
+    pub site: u16,
+    /// The subroutine or the interrupt handler
+    pub target: u16,
+    /// Where the matching return should continue
+    pub ret: u16,
+    /// The stack pointer after the return address (and P) were pushed
+    pub sp: u8,
+}
+
+/// Whether `sp` is above `than` on the stack, counting the wrap from $01ff to $0100
+pub(crate) fn above(sp: u8, than: u8) -> bool {
+    (sp.wrapping_sub(than) as i8) > 0
+}
+
+/// A return that doesn't match the shadow call stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Divergence {
+    /// No frame was pushed at this stack pointer, e.g. an RTS used as an indirect jump
+    Unmatched { pc: u16, to: u16 },
+    /// The return address on the stack was changed
+    Redirected { pc: u16, to: u16, expected: u16 },
+    /// The return skipped frames whose return addresses were pulled, e.g. PLA PLA RTS
+    Unwound { pc: u16, frames: usize },
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Divergence::Unmatched { pc, to } => {
+                write!(f, "{pc:04x}: return to {to:04x} without a matching call")
+            }
+            Divergence::Redirected { pc, to, expected } => write!(
+                f,
+                "{pc:04x}: return to {to:04x}, the call was to return to {expected:04x}"
+            ),
+            Divergence::Unwound { pc, frames } => {
+                write!(f, "{pc:04x}: return skipped {frames} frame(s)")
+            }
+        }
+    }
+}
+
+/// What a step did to the shadow stack, for the undo log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum CallChange {
+    Push,
+    Pop(Vec<Frame>),
+}
+
+/// The calls and interrupts the program is in, tracked from JSR, BRK, IRQ and NMI to
+/// RTS and RTI independently of what the code does to the stack
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    divergences: Vec<Divergence>,
+}
+
+impl CallStack {
+    /// The frames, outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns and clears the divergences seen since the last call
+    pub fn take_divergences(&mut self) -> Vec<Divergence> {
+        std::mem::take(&mut self.divergences)
+    }
+
+    /// Forget the frames, when the stack is reset or replaced
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub(crate) fn push(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// A return at `pc` with the stack pointer at `sp` before pulling, going to `to`.
+    /// Returns the frames popped.
+    pub(crate) fn ret(&mut self, pc: u16, sp: u8, to: u16) -> Vec<Frame> {
+        let Some(i) = self.frames.iter().rposition(|frame| frame.sp == sp) else {
+            self.diverge(Divergence::Unmatched { pc, to });
+            return Vec::new();
+        };
+        let mut popped = self.frames.split_off(i);
+        popped.reverse();
+        if popped.len() > 1 {
+            let frames = popped.len() - 1;
+            self.diverge(Divergence::Unwound { pc, frames });
+        }
+        let expected = popped.last().map_or(to, |frame| frame.ret);
+        if expected != to {
+            self.diverge(Divergence::Redirected { pc, to, expected });
+        }
+        popped
+    }
+
+    /// Undo a step's change
+    pub(crate) fn undo(&mut self, change: CallChange) {
+        match change {
+            CallChange::Push => {
+                self.frames.pop();
+            }
+            CallChange::Pop(popped) => self.frames.extend(popped.into_iter().rev()),
+        }
+    }
+
+    fn diverge(&mut self, divergence: Divergence) {
+        warn!("[callstack] {divergence}");
+        if self.divergences.len() < MAX_DIVERGENCES {
+            self.divergences.push(divergence);
+        }
+    }
+}
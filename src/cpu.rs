@@ -0,0 +1,1765 @@
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use bitflags::bitflags;
+use log::{debug, trace};
+
+use crate::{
+    bus::{ADDRESS_SPACE, Bus, MemoryMap},
+    callstack::{CallChange, CallStack, Frame, FrameKind},
+    history::{History, Snapshot, Undo},
+    opcode::opcodes,
+    operation::{AddressingMode, Index, Operation, parse_opcode},
+    profile::Profiler,
+    symbols::Symbols,
+    watch::{Access, Watchpoints},
+};
+
+enum Operand {
+    Value(u8),
+    Address(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cpu {
+    /// The original NMOS 6502
+    Nmos,
+    /// The WDC 65C02 with the Rockwell bit instructions
+    Cmos,
+}
+
+impl FromStr for Cpu {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "6502" | "nmos" => Ok(Cpu::Nmos),
+            "65c02" | "cmos" => Ok(Cpu::Cmos),
+            other => anyhow::bail!("unknown cpu {}, expected 6502 or 65c02", other),
+        }
+    }
+}
+
+impl Display for Cpu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cpu::Nmos => write!(f, "6502"),
+            Cpu::Cmos => write!(f, "65c02"),
+        }
+    }
+}
+
+pub struct Machine<B: Bus = MemoryMap> {
+    acc: u8,
+    x: u8,
+    y: u8,
+    flags: Flags,
+    sp: usize,
+    pc: usize,
+    bpc: usize,
+    cycles: u64,
+    page_crossed: bool,
+    cpu: Cpu,
+    undocumented: bool,
+    waiting: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    watchpoints: Watchpoints,
+    history: Option<History>,
+    profiler: Option<Profiler>,
+    call_stack: CallStack,
+    symbols: Symbols,
+    bus: B,
+}
+
+const STACK: usize = 0x100;
+const BIT7: u8 = 0x80;
+const BIT6: u8 = 0x40;
+const BIT0: u8 = 0x01;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Flags: u8 {
+        const CARRY = 0b0000_0001;
+        const ZERO = 0b0000_0010;
+        const INTERRUPT_DISABLE = 0b0000_0100;
+        const DECIMAL = 0b0000_1000;
+        const BREAK = 0b0001_0000;
+        /// Always set when P is pushed, not stored in the register
+        const UNUSED = 0b0010_0000;
+        const OVERFLOW = 0b0100_0000;
+        const NEGATIVE = 0b1000_0000;
+    }
+}
+
+pub const NMI_VECTOR: usize = 0xFFFA;
+pub const RESET_VECTOR: usize = 0xFFFC;
+pub const IRQ_VECTOR: usize = 0xFFFE;
+/// The bits the unstable LXA and XAA OR into A before the AND, chip dependent
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+/// A snapshot of the programmer visible registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub acc: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub flags: Flags,
+}
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pc:{:0>4x} acc:{:0>2x} x:{:0>2x} y:{:0>2x} sp:{:0>2x} p:{:0>8b}",
+            self.pc,
+            self.acc,
+            self.x,
+            self.y,
+            self.sp,
+            self.flags.bits()
+        )
+    }
+}
+
+/// Configures the processor of a [`Machine`] before plugging in its bus
+pub struct MachineBuilder {
+    cpu: Cpu,
+    undocumented: bool,
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        MachineBuilder {
+            cpu: Cpu::Nmos,
+            undocumented: true,
+        }
+    }
+}
+
+impl MachineBuilder {
+    pub fn cpu(mut self, cpu: Cpu) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    /// Whether the undocumented NMOS opcodes are decoded, the default
+    pub fn undocumented(mut self, enabled: bool) -> Self {
+        self.undocumented = enabled;
+        self
+    }
+
+    pub fn build<B: Bus>(self, bus: B) -> Machine<B> {
+        Machine {
+            acc: 0,
+            x: 0,
+            y: 0,
+            flags: Flags::empty(),
+            sp: 0xff,
+            pc: 0,
+            bpc: 0,
+            cycles: 0,
+            page_crossed: false,
+            cpu: self.cpu,
+            undocumented: self.undocumented,
+            waiting: false,
+            nmi_line: false,
+            nmi_pending: false,
+            watchpoints: Watchpoints::default(),
+            history: None,
+            profiler: None,
+            call_stack: CallStack::default(),
+            symbols: Symbols::default(),
+            bus,
+        }
+    }
+}
+
+impl Machine {
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::default()
+    }
+}
+
+impl<B: Bus> Machine<B> {
+    pub fn cpu(&self) -> Cpu {
+        self.cpu
+    }
+
+    /// Clock cycles elapsed since the machine was built
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            acc: self.acc,
+            x: self.x,
+            y: self.y,
+            sp: self.sp as u8,
+            pc: self.pc as u16,
+            flags: self.flags,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: Registers) {
+        self.acc = registers.acc;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.sp = registers.sp as usize;
+        self.pc = registers.pc as usize;
+        self.bpc = self.pc;
+        self.flags = registers.flags - Flags::BREAK - Flags::UNUSED;
+    }
+
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Watchpoints {
+        &mut self.watchpoints
+    }
+
+    /// The shadow call stack, tracked from the calls, interrupts and returns
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    pub fn call_stack_mut(&mut self) -> &mut CallStack {
+        &mut self.call_stack
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut Symbols {
+        &mut self.symbols
+    }
+
+    /// Keep an undo log of the last `steps` steps so they can be stepped back, 0 stops recording
+    pub fn record_history(&mut self, steps: usize) {
+        self.history = (steps > 0).then(|| History::new(steps));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Start counting the executions and cycles of every address and routine from
+    /// scratch, or stop and drop the counts. Stepping back doesn't uncount.
+    pub fn record_profile(&mut self, enabled: bool) {
+        self.profiler = enabled.then(Profiler::new);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Undo the last recorded step, restoring the registers and the memory it wrote.
+    /// Device side effects such as reading the keyboard are not undone.
+    pub fn step_back(&mut self) -> Option<Undo> {
+        let mut undo = self.history.as_mut()?.pop()?;
+        for &(addr, old) in undo.writes().iter().rev() {
+            self.bus.poke(addr, old);
+        }
+        if let Some(change) = undo.call.take() {
+            self.call_stack.undo(change);
+        }
+        self.restore(undo.snapshot);
+        Some(undo)
+    }
+
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) {
+        self.acc = snapshot.acc;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.flags = snapshot.flags;
+        self.sp = snapshot.sp;
+        self.pc = snapshot.pc;
+        self.bpc = snapshot.bpc;
+        self.cycles = snapshot.cycles;
+        self.waiting = snapshot.waiting;
+        self.nmi_line = snapshot.nmi_line;
+        self.nmi_pending = snapshot.nmi_pending;
+    }
+
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            acc: self.acc,
+            x: self.x,
+            y: self.y,
+            flags: self.flags,
+            sp: self.sp,
+            pc: self.pc,
+            bpc: self.bpc,
+            cycles: self.cycles,
+            waiting: self.waiting,
+            nmi_line: self.nmi_line,
+            nmi_pending: self.nmi_pending,
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Read memory without side effects
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
+    }
+
+    /// Write memory bypassing write protection
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.bus.poke(addr, value)
+    }
+
+    pub fn dump_memory(&self, r: RangeInclusive<u16>, out: &mut impl Write) -> io::Result<()> {
+        let per_row = 16;
+        let mut row_cursor = 0;
+        for i in r {
+            // a row ends before a named address, and the name goes on its own line
+            if let Some(name) = self.symbols.name(i) {
+                if row_cursor != 0 {
+                    row_cursor = 0;
+                    writeln!(out)?;
+                }
+                writeln!(out, "{name}:")?;
+            }
+            if row_cursor == 0 {
+                write!(out, "{i:0>4x}: ")?;
+            }
+            write!(out, "{:0>2x} ", self.peek(i))?;
+            row_cursor += 1;
+            if row_cursor == per_row {
+                row_cursor = 0;
+                writeln!(out)?;
+            }
+        }
+        if row_cursor != 0 {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// The 7 cycle reset sequence: three suppressed stack pushes, I set and pc loaded
+    /// from the reset vector. Memory and the A, X, Y registers are left untouched.
+    pub fn reset(&mut self) -> anyhow::Result<()> {
+        self.sp = self.sp.wrapping_sub(3) & 0xFF;
+        self.set_interrupt_disable();
+        if self.cpu == Cpu::Cmos {
+            self.cls_decimal();
+        }
+        self.waiting = false;
+        self.nmi_pending = false;
+        self.call_stack.clear();
+        let addr = self.read_memory_u16(RESET_VECTOR)? as usize;
+        self.goto(addr)?;
+        self.cycles += 7;
+        Ok(())
+    }
+
+    fn set_acc(&mut self, value: u8) {
+        self.acc = value;
+        self.update_zero_and_negative_flags(self.acc);
+    }
+
+    fn set_x(&mut self, value: u8) {
+        self.x = value;
+        self.update_zero_and_negative_flags(self.x);
+    }
+
+    fn set_y(&mut self, value: u8) {
+        self.y = value;
+        self.update_zero_and_negative_flags(self.y);
+    }
+
+    #[inline]
+    fn is_carry(&self) -> bool {
+        self.flags.contains(Flags::CARRY)
+    }
+
+    #[inline]
+    fn set_carry(&mut self) {
+        self.flags.insert(Flags::CARRY);
+    }
+
+    #[inline]
+    fn cls_carry(&mut self) {
+        self.flags.remove(Flags::CARRY);
+    }
+
+    #[inline]
+    fn is_zero(&self) -> bool {
+        self.flags.contains(Flags::ZERO)
+    }
+
+    #[inline]
+    fn set_zero(&mut self) {
+        self.flags.insert(Flags::ZERO)
+    }
+
+    #[inline]
+    fn cls_zero(&mut self) {
+        self.flags.remove(Flags::ZERO)
+    }
+
+    #[inline]
+    fn is_interrupt_disable(&self) -> bool {
+        self.flags.contains(Flags::INTERRUPT_DISABLE)
+    }
+
+    #[inline]
+    fn set_interrupt_disable(&mut self) {
+        self.flags.insert(Flags::INTERRUPT_DISABLE);
+    }
+
+    #[inline]
+    fn cls_interrupt_disable(&mut self) {
+        self.flags.remove(Flags::INTERRUPT_DISABLE);
+    }
+
+    #[inline]
+    fn is_decimal(&self) -> bool {
+        self.flags.contains(Flags::DECIMAL)
+    }
+
+    #[inline]
+    fn set_decimal(&mut self) {
+        self.flags.insert(Flags::DECIMAL);
+    }
+
+    #[inline]
+    fn cls_decimal(&mut self) {
+        self.flags.remove(Flags::DECIMAL);
+    }
+
+    /*fn is_break(&self) -> bool {
+        self.p & BREAK_BIT != 0
+    }*/
+
+    /*fn set_break(&mut self) {
+        self.p |= BREAK_BIT;
+    }*/
+
+    /*fn cls_break(&mut self) {
+        self.p &= BREAK_MASK;
+    }*/
+
+    #[inline]
+    fn is_overflow(&self) -> bool {
+        self.flags.contains(Flags::OVERFLOW)
+    }
+
+    #[inline]
+    fn set_overflow(&mut self) {
+        self.flags.insert(Flags::OVERFLOW);
+    }
+
+    #[inline]
+    fn cls_overflow(&mut self) {
+        self.flags.remove(Flags::OVERFLOW);
+    }
+
+    #[inline]
+    fn is_negative(&self) -> bool {
+        self.flags.contains(Flags::NEGATIVE)
+    }
+
+    #[inline]
+    fn set_negative(&mut self) {
+        self.flags.insert(Flags::NEGATIVE);
+    }
+
+    #[inline]
+    fn cls_negative(&mut self) {
+        self.flags.remove(Flags::NEGATIVE);
+    }
+
+    fn write_memory(&mut self, addr: usize, value: u8) -> anyhow::Result<()> {
+        if self.check_addr(addr) {
+            if self.watchpoints.armed() || self.history.is_some() {
+                let old = self.bus.peek(addr as u16);
+                self.watchpoints
+                    .check(self.bpc as u16, addr as u16, Access::Write, old, value);
+                if let Some(history) = &mut self.history {
+                    history.record_write(addr as u16, old);
+                }
+            }
+            self.bus.write(addr as u16, value);
+            Ok(())
+        } else {
+            anyhow::bail!("write memory overflow addr: {:x}", addr);
+        }
+    }
+
+    fn read_memory(&mut self, addr: usize) -> anyhow::Result<u8> {
+        if self.check_addr(addr) {
+            let value = self.bus.read(addr as u16);
+            if self.watchpoints.armed() {
+                self.watchpoints
+                    .check(self.bpc as u16, addr as u16, Access::Read, value, value);
+            }
+            Ok(value)
+        } else {
+            anyhow::bail!("get memory overflow addr:{:x}", addr);
+        }
+    }
+
+    fn read_memory_u16(&mut self, addr: usize) -> anyhow::Result<u16> {
+        let lsb = self.read_memory(addr)?;
+        let msb = self.read_memory((addr + 1) & 0xFFFF)?;
+        Ok(u16::from_le_bytes([lsb, msb]))
+    }
+
+    /// Pointers in the zero page wrap around within it: ($FF) reads $FF and $00
+    fn read_zero_page_u16(&mut self, addr: u8) -> anyhow::Result<u16> {
+        let lsb = self.read_memory(addr as usize)?;
+        let msb = self.read_memory(addr.wrapping_add(1) as usize)?;
+        Ok(u16::from_le_bytes([lsb, msb]))
+    }
+
+    #[inline]
+    fn check_addr(&self, addr: usize) -> bool {
+        addr < ADDRESS_SPACE
+    }
+
+    /*fn store_flag(&mut self) -> anyhow::Result<()> {
+        self.stack_push(self.p)
+    }*/
+
+    #[inline]
+    fn store_flag_with(&mut self, flags: Flags) -> anyhow::Result<()> {
+        self.stack_push((self.flags | Flags::UNUSED | flags).bits())
+    }
+
+    /// B and bit 5 only exist on the stack, PLP and RTI drop them
+    fn restore_flag(&mut self) -> anyhow::Result<()> {
+        let bits = self.stack_pop()?;
+        self.flags = Flags::from_bits_truncate(bits) - Flags::BREAK - Flags::UNUSED;
+        Ok(())
+    }
+
+    /// Push pc and P, then jump through the vector. B is only pushed set by BRK
+    fn interrupt(&mut self, vector: usize, flags: Flags, kind: FrameKind) -> anyhow::Result<()> {
+        let ret = self.pc as u16;
+        self.store_pc()?;
+        self.store_flag_with(flags)?;
+        self.set_interrupt_disable();
+        if self.cpu == Cpu::Cmos {
+            self.cls_decimal();
+        }
+        let addr = self.read_memory_u16(vector)? as usize;
+        self.push_frame(Frame {
+            kind,
+            site: if kind == FrameKind::Brk {
+                self.bpc
+            } else {
+                self.pc
+            } as u16,
+            target: addr as u16,
+            ret,
+            sp: self.sp as u8,
+        });
+        self.goto(addr)
+    }
+
+    fn push_frame(&mut self, frame: Frame) {
+        self.call_stack.push(frame);
+        if let Some(history) = &mut self.history {
+            history.record_call(CallChange::Push);
+        }
+    }
+
+    /// Match the return at `site` with the shadow stack, `sp` is the stack pointer
+    /// before pulling
+    fn pop_frames(&mut self, site: usize, sp: usize) {
+        let popped = self.call_stack.ret(site as u16, sp as u8, self.pc as u16);
+        if let Some(history) = &mut self.history
+            && !popped.is_empty()
+        {
+            history.record_call(CallChange::Pop(popped));
+        }
+    }
+
+    /// Sample the interrupt lines between instructions. NMI is edge triggered,
+    /// IRQ level triggered and masked by I. Returns whether an interrupt was taken
+    fn poll_interrupts(&mut self) -> anyhow::Result<bool> {
+        let nmi = self.bus.nmi();
+        if nmi && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = nmi;
+        if self.nmi_pending {
+            trace!("[interrupt] nmi");
+            self.nmi_pending = false;
+            self.waiting = false;
+            self.interrupt(NMI_VECTOR, Flags::empty(), FrameKind::Nmi)?;
+            self.cycles += 7;
+            return Ok(true);
+        }
+        if self.bus.irq() {
+            // WAI resumes on IRQ even when it is masked
+            self.waiting = false;
+            if !self.is_interrupt_disable() {
+                trace!("[interrupt] irq");
+                self.interrupt(IRQ_VECTOR, Flags::empty(), FrameKind::Irq)?;
+                self.cycles += 7;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn store_pc(&mut self) -> anyhow::Result<()> {
+        self.stack_push_u16(self.pc as u16)
+    }
+
+    fn restore_pc(&mut self) -> anyhow::Result<()> {
+        let pc = self.stack_pop_u16()?;
+        self.pc = pc as usize;
+        self.bpc = pc as usize;
+        Ok(())
+    }
+
+    /// The stack lives in page 1, the stack pointer wraps around within it
+    fn stack_push(&mut self, value: u8) -> anyhow::Result<()> {
+        self.write_memory(STACK + self.sp, value)?;
+        self.sp = self.sp.wrapping_sub(1) & 0xFF;
+        Ok(())
+    }
+
+    fn stack_pop(&mut self) -> anyhow::Result<u8> {
+        self.sp = (self.sp + 1) & 0xFF;
+        self.read_memory(STACK + self.sp)
+    }
+
+    fn stack_push_u16(&mut self, value: u16) -> anyhow::Result<()> {
+        self.stack_push(((value >> 8) & 0xFF) as u8)?;
+        self.stack_push((value & 0xFF) as u8)?;
+        Ok(())
+    }
+
+    fn stack_pop_u16(&mut self) -> anyhow::Result<u16> {
+        let lsb = self.stack_pop()?;
+        let msb = self.stack_pop()?;
+        Ok(u16::from_le_bytes([lsb, msb]))
+    }
+
+    pub fn load(&mut self, addr: usize, data: &[u8]) -> anyhow::Result<()> {
+        if addr + data.len() > ADDRESS_SPACE {
+            anyhow::bail!("insufficient memory for loading");
+        }
+        for (i, &byte) in data.iter().enumerate() {
+            self.bus.poke((addr + i) as u16, byte);
+        }
+        Ok(())
+    }
+
+    pub fn load_jmp(&mut self, addr: usize, data: &[u8]) -> anyhow::Result<()> {
+        self.load(addr, data)?;
+        self.jump(addr as u16);
+        Ok(())
+    }
+
+    /// Continue at `addr`, leaving the other registers alone
+    pub fn jump(&mut self, addr: u16) {
+        self.bpc = addr as usize;
+        self.pc = addr as usize;
+    }
+
+    /// Fetch, decode and execute the instruction at pc, or take a pending interrupt
+    pub fn step(&mut self) -> anyhow::Result<Status> {
+        let Some(profiler) = &mut self.profiler else {
+            return self.step_instruction();
+        };
+        profiler.enter(self.call_stack.frames());
+        let (pc, cycles, depth) = (self.pc as u16, self.cycles, self.call_stack.depth());
+        let executed = !self.waiting;
+        let status = self.step_instruction();
+        if let Some(profiler) = &mut self.profiler {
+            let frames = self.call_stack.frames();
+            profiler.record(pc, self.cycles - cycles, executed, depth, frames);
+        }
+        status
+    }
+
+    fn step_instruction(&mut self) -> anyhow::Result<Status> {
+        if self.history.is_some() {
+            let snapshot = self.snapshot();
+            if let Some(history) = &mut self.history {
+                history.begin(snapshot);
+            }
+        }
+        if self.poll_interrupts()? {
+            return Ok(Status::Cont);
+        }
+        if self.waiting {
+            self.cycles += 1;
+            return Ok(Status::Cont);
+        }
+        let opcode = self.bus.peek(self.pc as u16);
+        let (cpu, undocumented) = (self.cpu, self.undocumented);
+        let Some(op) = parse_opcode(self, cpu, undocumented)? else {
+            return Ok(Status::Halt);
+        };
+        debug!(
+            "{:x}{}: {}",
+            self.bpc,
+            self.symbols
+                .label(self.bpc as u16)
+                .map(|label| format!(" <{label}>"))
+                .unwrap_or_default(),
+            self.symbols.operation(&op, self.bpc as u16)
+        );
+        self.cycles += opcodes(cpu)[opcode as usize].cycles as u64;
+        let status = self.execute(op)?;
+        debug!(
+            "- acc:{:x}, x:{:x}, y:{:x}, sp:{:x}, p:{:0>8b}, cyc:{} -",
+            self.acc,
+            self.x,
+            self.y,
+            self.sp,
+            self.flags.bits(),
+            self.cycles
+        );
+        Ok(status)
+    }
+
+    /// Step until at least `cycles` clock cycles elapsed or the processor halts
+    pub fn run_for_cycles(&mut self, cycles: u64) -> anyhow::Result<Status> {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            if let Status::Halt = self.step()? {
+                return Ok(Status::Halt);
+            }
+        }
+        Ok(Status::Cont)
+    }
+
+    fn goto(&mut self, addr: usize) -> anyhow::Result<()> {
+        if self.check_addr(addr) {
+            self.pc = addr;
+            self.bpc = addr;
+            Ok(())
+        } else {
+            anyhow::bail!("Invalid goto addr {}", addr);
+        }
+    }
+
+    /// A taken branch costs one more cycle, two when it lands on another page
+    fn branch(&mut self, addr: usize) -> anyhow::Result<()> {
+        self.cycles += if same_page(self.pc, addr) { 1 } else { 2 };
+        self.goto(addr)
+    }
+
+    /// BBR/BBS: test a zero page bit and branch when it matches `set`
+    fn branch_on_bit(&mut self, bit: u8, mode: AddressingMode, set: bool) -> anyhow::Result<()> {
+        let AddressingMode::ZeroPageRelative(zp, rel) = mode else {
+            anyhow::bail!("invalid operand in Bbr/Bbs");
+        };
+        let val = self.read_memory(zp as usize)?;
+        if (val & (1 << bit) != 0) == set {
+            if let Operand::Address(addr) = self.get_operand(AddressingMode::Relative(rel))? {
+                self.branch(addr)?;
+            }
+        } else {
+            self.advance();
+        }
+        Ok(())
+    }
+
+    fn carry_bit(&self) -> u8 {
+        if self.is_carry() { 1 } else { 0 }
+    }
+
+    fn advance(&mut self) {
+        self.bpc = self.pc;
+    }
+
+    fn get_operand(&mut self, mode: AddressingMode) -> anyhow::Result<Operand> {
+        use AddressingMode::*;
+        use Operand::*;
+        self.page_crossed = false;
+        let val = match mode {
+            Immediate(n) => Value(n),
+            ZeroPage(a, idx) => match idx {
+                Index::None => Address(a as usize),
+                Index::X => Address(a.wrapping_add(self.x) as usize),
+                Index::Y => Address(a.wrapping_add(self.y) as usize),
+            },
+            Relative(ra) => Address(self.pc.wrapping_add_signed(ra as isize) & 0xFFFF),
+            Absolute(a, idx) => match idx {
+                Index::None => Address(a as usize),
+                Index::X => {
+                    let addr = a.wrapping_add(self.x as u16) as usize;
+                    self.page_crossed = !same_page(a as usize, addr);
+                    Address(addr)
+                }
+                Index::Y => {
+                    let addr = a.wrapping_add(self.y as u16) as usize;
+                    self.page_crossed = !same_page(a as usize, addr);
+                    Address(addr)
+                }
+            },
+            Indirect(a) => {
+                // the NMOS chip doesn't carry into the high byte: JMP ($10FF) reads $10FF and $1000
+                let msb_addr = match self.cpu {
+                    Cpu::Nmos => (a & 0xFF00) | (a.wrapping_add(1) & 0x00FF),
+                    Cpu::Cmos => a.wrapping_add(1),
+                };
+                let lsb = self.read_memory(a as usize)?;
+                let msb = self.read_memory(msb_addr as usize)?;
+                Address(u16::from_le_bytes([lsb, msb]) as usize)
+            }
+            IndexedIndirect(a) => {
+                Address(self.read_zero_page_u16(a.wrapping_add(self.x))? as usize)
+            }
+            IndirectIndexed(a) => {
+                let addr = self.read_zero_page_u16(a)?;
+                let indexed = addr.wrapping_add(self.y as u16) as usize;
+                self.page_crossed = !same_page(addr as usize, indexed);
+                Address(indexed)
+            }
+            ZeroPageIndirect(a) => Address(self.read_zero_page_u16(a)? as usize),
+            AbsoluteIndexedIndirect(a) => {
+                let addr = a.wrapping_add(self.x as u16) as usize;
+                Address(self.read_memory_u16(addr)? as usize)
+            }
+            _ => anyhow::bail!("unsupported addressing mode"),
+        };
+        Ok(val)
+    }
+
+    /// Resolve the operand of a read instruction, indexing across a page costs one more cycle
+    fn get_operand_value(&mut self, mode: AddressingMode) -> anyhow::Result<u8> {
+        let operand = self.get_operand(mode)?;
+        if self.page_crossed {
+            self.cycles += 1;
+        }
+        use Operand::*;
+        match operand {
+            Address(addr) => self.read_memory(addr),
+            Value(v) => Ok(v),
+        }
+    }
+
+    fn adc(&mut self, mem_val: u8) {
+        let acc = self.acc;
+        let carry = self.carry_bit();
+        let (result, overflow) = acc.overflowing_add(mem_val);
+        let (result_c, overflow_c) = result.overflowing_add(carry);
+        self.set_acc(result_c);
+        if overflow || overflow_c {
+            self.set_carry();
+        } else {
+            self.cls_carry();
+        }
+        let result_sign = sign_bit(result_c);
+        if result_sign != sign_bit(mem_val) && result_sign != sign_bit(acc) {
+            self.set_overflow();
+        } else {
+            self.cls_overflow();
+        }
+        if self.is_decimal() {
+            self.adc_decimal(acc, mem_val, carry);
+            self.cmos_decimal_fixup();
+        }
+    }
+
+    fn sbc(&mut self, mem_val: u8) {
+        let acc = self.acc;
+        let inv_carry = if self.is_carry() { 0u8 } else { 1u8 };
+        let (result, underflow) = acc.overflowing_sub(mem_val);
+        let (result_c, underflow_c) = result.overflowing_sub(inv_carry);
+        self.set_acc(result_c);
+        if underflow || underflow_c {
+            self.cls_carry();
+        } else {
+            self.set_carry();
+        }
+        let result_sign = sign_bit(result_c);
+        if result_sign != sign_bit(acc) && result_sign == sign_bit(mem_val) {
+            self.set_overflow();
+        } else {
+            self.cls_overflow();
+        }
+        if self.is_decimal() {
+            self.sbc_decimal(acc, mem_val, 1 - inv_carry);
+            self.cmos_decimal_fixup();
+        }
+    }
+
+    /// Shifts and rotates with absolute,X take one more cycle on a page crossing on the 65C02
+    fn cmos_shift_penalty(&mut self) {
+        if self.cpu == Cpu::Cmos && self.page_crossed {
+            self.cycles += 1;
+        }
+    }
+
+    /// The 65C02 sets N and Z from the decimal result at the cost of one more cycle
+    fn cmos_decimal_fixup(&mut self) {
+        if self.cpu == Cpu::Cmos {
+            self.update_zero_and_negative_flags(self.acc);
+            self.cycles += 1;
+        }
+    }
+
+    /// Z = !(A & M), as set by BIT, TRB and TSB
+    fn test_bits(&mut self, acc: u8, val: u8) {
+        if acc & val == 0 {
+            self.set_zero();
+        } else {
+            self.cls_zero();
+        }
+    }
+
+    fn compare(&mut self, reg: u8, val: u8) {
+        if reg > val {
+            self.set_carry();
+            self.cls_zero();
+        } else if reg == val {
+            self.set_carry();
+            self.set_zero();
+        } else {
+            self.cls_carry();
+            self.cls_zero();
+        }
+        if is_negative(reg.wrapping_sub(val)) {
+            self.set_negative();
+        } else {
+            self.cls_negative();
+        }
+    }
+
+    /// Shift left through carry, flags other than C are left to the caller
+    fn shift_left(&mut self, val: u8, carry_in: u8) -> u8 {
+        if is_negative(val) {
+            self.set_carry();
+        } else {
+            self.cls_carry();
+        }
+        (val << 1) | carry_in
+    }
+
+    /// Shift right through carry, flags other than C are left to the caller
+    fn shift_right(&mut self, val: u8, carry_in: u8) -> u8 {
+        if val & BIT0 == BIT0 {
+            self.set_carry();
+        } else {
+            self.cls_carry();
+        }
+        (val >> 1) | (carry_in << 7)
+    }
+
+    /// Read the memory operand, write back `f` of it and return the new value
+    fn read_modify_write(
+        &mut self,
+        mode: AddressingMode,
+        f: impl FnOnce(&mut Self, u8) -> u8,
+    ) -> anyhow::Result<u8> {
+        if let Operand::Address(addr) = self.get_operand(mode)? {
+            let val = self.read_memory(addr)?;
+            let new_val = f(self, val);
+            self.write_memory(addr, new_val)?;
+            Ok(new_val)
+        } else {
+            anyhow::bail!("invalid read-modify-write operand");
+        }
+    }
+
+    /// The unstable SHA/SHX/SHY/TAS stores, modelled after the common NMOS behaviour:
+    /// the value is ANDed with the high byte of the base address plus one, and when
+    /// indexing crosses a page the high byte of the target address is replaced by the value
+    fn store_unstable(&mut self, mode: AddressingMode, value: u8) -> anyhow::Result<()> {
+        let (base, index) = match mode {
+            AddressingMode::Absolute(a, Index::X) => (a as usize, self.x),
+            AddressingMode::Absolute(a, Index::Y) => (a as usize, self.y),
+            AddressingMode::IndirectIndexed(a) => (self.read_zero_page_u16(a)? as usize, self.y),
+            _ => anyhow::bail!("invalid operand for unstable store"),
+        };
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let mut addr = (base + index as usize) & 0xFFFF;
+        if !same_page(base, addr) {
+            addr = ((value as usize) << 8) | (addr & 0xFF);
+        }
+        self.write_memory(addr, value)
+    }
+
+    /// ARR: AND then ROR A, with C and V taken from bits 6 and 5 of the result,
+    /// in decimal mode the NMOS chip additionally BCD-fixes both nibbles
+    fn arr(&mut self, val: u8) {
+        let and = self.acc & val;
+        let carry = self.carry_bit();
+        let mut result = (and >> 1) | (carry << 7);
+        self.update_zero_and_negative_flags(result);
+        if self.is_decimal() {
+            if (result ^ and) & BIT6 != 0 {
+                self.set_overflow();
+            } else {
+                self.cls_overflow();
+            }
+            let (high, low) = (and >> 4, and & 0x0F);
+            if low + (low & 1) > 5 {
+                result = (result & 0xF0) | (result.wrapping_add(6) & 0x0F);
+            }
+            if high + (high & 1) > 5 {
+                self.set_carry();
+                result = result.wrapping_add(0x60);
+            } else {
+                self.cls_carry();
+            }
+        } else {
+            if result & BIT6 != 0 {
+                self.set_carry();
+            } else {
+                self.cls_carry();
+            }
+            if ((result >> 6) ^ (result >> 5)) & 1 != 0 {
+                self.set_overflow();
+            } else {
+                self.cls_overflow();
+            }
+        }
+        self.acc = result;
+    }
+
+    /// Decimal mode ADC: Z is left from the binary sum,
+    /// N and V come from the intermediate result before the high nibble is adjusted
+    fn adc_decimal(&mut self, acc: u8, val: u8, carry: u8) {
+        let mut low = (acc & 0x0F) as i16 + (val & 0x0F) as i16 + carry as i16;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (acc & 0xF0) as i16 + (val & 0xF0) as i16 + low;
+        let signed_sum = (acc & 0xF0) as i8 as i16 + (val & 0xF0) as i8 as i16 + low;
+        if sum & BIT7 as i16 != 0 {
+            self.set_negative();
+        } else {
+            self.cls_negative();
+        }
+        if (-128..=127).contains(&signed_sum) {
+            self.cls_overflow();
+        } else {
+            self.set_overflow();
+        }
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        if sum >= 0x100 {
+            self.set_carry();
+        } else {
+            self.cls_carry();
+        }
+        self.acc = sum as u8;
+    }
+
+    /// Decimal mode SBC, the flags are left from the binary subtraction.
+    /// The 65C02 adjusts the binary difference instead of working nibble by nibble
+    fn sbc_decimal(&mut self, acc: u8, val: u8, carry: u8) {
+        let mut low = (acc & 0x0F) as i16 - (val & 0x0F) as i16 + carry as i16 - 1;
+        let diff = match self.cpu {
+            Cpu::Nmos => {
+                if low < 0 {
+                    low = ((low - 0x06) & 0x0F) - 0x10;
+                }
+                let mut diff = (acc & 0xF0) as i16 - (val & 0xF0) as i16 + low;
+                if diff < 0 {
+                    diff -= 0x60;
+                }
+                diff
+            }
+            Cpu::Cmos => {
+                let mut diff = acc as i16 - val as i16 + carry as i16 - 1;
+                if diff < 0 {
+                    diff -= 0x60;
+                }
+                if low < 0 {
+                    diff -= 0x06;
+                }
+                diff
+            }
+        };
+        self.acc = diff as u8;
+    }
+
+    fn update_zero_and_negative_flags(&mut self, val: u8) {
+        if val == 0 {
+            self.set_zero();
+        } else {
+            self.cls_zero();
+        }
+        // N
+        if is_negative(val) {
+            self.set_negative();
+        } else {
+            self.cls_negative();
+        }
+    }
+
+    fn execute(&mut self, op: Operation) -> anyhow::Result<Status> {
+        use AddressingMode::*;
+        use Operand::*;
+        use Operation::*;
+        match op {
+            Adc(mode) => {
+                let mem_val = self.get_operand_value(mode)?;
+                self.adc(mem_val);
+                self.advance();
+            }
+            And(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(self.acc & val);
+                self.advance();
+            }
+            Asl(mode) => {
+                if let Accumulator = mode {
+                    let sign_bit = sign_bit(self.acc);
+                    self.set_acc(self.acc << 1);
+                    if sign_bit != 0 {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    self.cmos_shift_penalty();
+                    let mem_val = self.read_memory(addr)?;
+                    if is_negative(mem_val) {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    let new_val = mem_val << 1;
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Asl instruction");
+                }
+                self.advance();
+            }
+            Bit(mode) => {
+                let immediate = matches!(mode, Immediate(_));
+                let mem_val = self.get_operand_value(mode)?;
+                self.test_bits(self.acc, mem_val);
+                // BIT #imm of the 65C02 only affects Z
+                if immediate {
+                    self.advance();
+                    return Ok(Status::Cont);
+                }
+                if is_negative(mem_val) {
+                    self.set_negative();
+                } else {
+                    self.cls_negative();
+                }
+                if mem_val & BIT6 == BIT6 {
+                    self.set_overflow();
+                } else {
+                    self.cls_overflow();
+                }
+                self.advance();
+            }
+            Bpl(mode) => {
+                if !self.is_negative() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bpl");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bmi(mode) => {
+                if self.is_negative() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Nmi");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bvc(mode) => {
+                if !self.is_overflow() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bvc");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bvs(mode) => {
+                if self.is_overflow() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bvc");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bcc(mode) => {
+                if !self.is_carry() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bcc");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bcs(mode) => {
+                if self.is_carry() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bcs");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Bne(mode) => {
+                if !self.is_zero() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Bne");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Beq(mode) => {
+                if self.is_zero() {
+                    if let Address(addr) = self.get_operand(mode)? {
+                        self.branch(addr)?;
+                    } else {
+                        anyhow::bail!("invalid address in Beq");
+                    }
+                } else {
+                    self.advance();
+                }
+            }
+            Brk => {
+                self.interrupt(IRQ_VECTOR, Flags::BREAK, FrameKind::Brk)?;
+            }
+            Cmp(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.compare(self.acc, val);
+                self.advance();
+            }
+            Cpx(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.compare(self.x, val);
+                self.advance();
+            }
+            Cpy(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.compare(self.y, val);
+                self.advance();
+            }
+            Dec(mode) => {
+                if let Accumulator = mode {
+                    self.set_acc(self.acc.wrapping_sub(1));
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    let mem_val = self.read_memory(addr)?;
+                    let new_val = mem_val.wrapping_sub(1);
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Dec instruction");
+                }
+                self.advance();
+            }
+            Eor(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(self.acc ^ val);
+                self.advance();
+            }
+            Clc => {
+                self.cls_carry();
+                self.advance();
+            }
+            Sec => {
+                self.set_carry();
+                self.advance();
+            }
+            Cli => {
+                self.cls_interrupt_disable();
+                self.advance();
+            }
+            Sei => {
+                self.set_interrupt_disable();
+                self.advance();
+            }
+            Clv => {
+                self.cls_overflow();
+                self.advance();
+            }
+            Cld => {
+                self.cls_decimal();
+                self.advance();
+            }
+            Sed => {
+                self.set_decimal();
+                self.advance();
+            }
+            Inc(mode) => {
+                if let Accumulator = mode {
+                    self.set_acc(self.acc.wrapping_add(1));
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    let mem_val = self.read_memory(addr)?;
+                    let new_val = mem_val.wrapping_add(1);
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Inc instruction");
+                }
+                self.advance();
+            }
+            Jmp(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.goto(addr)?;
+                } else {
+                    anyhow::bail!("invalid jump instruction");
+                }
+            }
+            Jsr(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    // the return address is pushed minus one, RTS adds it back
+                    self.stack_push_u16((self.pc as u16).wrapping_sub(1))?;
+                    self.push_frame(Frame {
+                        kind: FrameKind::Jsr,
+                        site: self.bpc as u16,
+                        target: addr as u16,
+                        ret: self.pc as u16,
+                        sp: self.sp as u8,
+                    });
+                    self.goto(addr)?;
+                } else {
+                    anyhow::bail!("invalid Jsr instruction");
+                }
+            }
+            Lda(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(val);
+                self.advance();
+            }
+            Ldx(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_x(val);
+                self.advance();
+            }
+            Ldy(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_y(val);
+                self.advance();
+            }
+            Lsr(mode) => {
+                if let Accumulator = mode {
+                    if self.acc & 1 != 0 {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    self.set_acc(self.acc >> 1);
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    self.cmos_shift_penalty();
+                    let val = self.read_memory(addr)?;
+                    if val & 1 != 0 {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    let new_val = val >> 1;
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Lsr instruction");
+                }
+                self.advance();
+            }
+            Nop => self.advance(),
+            Ora(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(self.acc | val);
+                self.advance();
+            }
+            Tax => {
+                self.set_x(self.acc);
+                self.advance();
+            }
+            Txa => {
+                self.set_acc(self.x);
+                self.advance();
+            }
+            Dex => {
+                self.set_x(self.x.wrapping_sub(1));
+                self.advance();
+            }
+            Inx => {
+                self.set_x(self.x.wrapping_add(1));
+                self.advance();
+            }
+            Tay => {
+                self.set_y(self.acc);
+                self.advance();
+            }
+            Tya => {
+                self.set_acc(self.y);
+                self.advance();
+            }
+            Dey => {
+                self.set_y(self.y.wrapping_sub(1));
+                self.advance();
+            }
+            Iny => {
+                self.set_y(self.y.wrapping_add(1));
+                self.advance();
+            }
+            Rol(mode) => {
+                let carry = self.carry_bit();
+                if let Accumulator = mode {
+                    if is_negative(self.acc) {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    self.set_acc((self.acc << 1) | carry);
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    self.cmos_shift_penalty();
+                    let mem_val = self.read_memory(addr)?;
+                    if is_negative(mem_val) {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    let new_val = (mem_val << 1) | carry;
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Rol instruction");
+                }
+                self.advance();
+            }
+            Ror(mode) => {
+                let high_bit: u8 = if self.is_carry() { BIT7 } else { 0 };
+                if let Accumulator = mode {
+                    if self.acc & BIT0 == BIT0 {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    self.set_acc((self.acc >> 1) | high_bit);
+                } else if let Address(addr) = self.get_operand(mode)? {
+                    self.cmos_shift_penalty();
+                    let mem_val = self.read_memory(addr)?;
+                    if mem_val & BIT0 == BIT0 {
+                        self.set_carry();
+                    } else {
+                        self.cls_carry();
+                    }
+                    let new_val = (mem_val >> 1) | high_bit;
+                    self.write_memory(addr, new_val)?;
+                    self.update_zero_and_negative_flags(new_val);
+                } else {
+                    anyhow::bail!("invalid Ror instruction");
+                }
+                self.advance();
+            }
+            Rti => {
+                let (site, sp) = (self.bpc, self.sp);
+                self.restore_flag()?;
+                self.restore_pc()?;
+                self.pop_frames(site, sp);
+            }
+            Rts => {
+                let (site, sp) = (self.bpc, self.sp);
+                self.restore_pc()?;
+                self.goto((self.pc + 1) & 0xFFFF)?;
+                self.pop_frames(site, sp);
+            }
+            Sbc(mode) => {
+                let mem_val = self.get_operand_value(mode)?;
+                self.sbc(mem_val);
+                self.advance();
+            }
+            Sta(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.write_memory(addr, self.acc)?;
+                } else {
+                    anyhow::bail!("invalid operaand in Sta");
+                }
+                self.advance();
+            }
+            Txs => {
+                self.sp = self.x as usize;
+                self.advance();
+            }
+            Tsx => {
+                self.set_x(self.sp as u8);
+                self.advance();
+            }
+            Pha => {
+                self.stack_push(self.acc)?;
+                self.advance();
+            }
+            Pla => {
+                let acc_val = self.stack_pop()?;
+                self.set_acc(acc_val);
+                self.advance();
+            }
+            Php => {
+                self.store_flag_with(Flags::BREAK)?;
+                self.advance();
+            }
+            Plp => {
+                self.restore_flag()?;
+                self.advance();
+            }
+            Stx(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.write_memory(addr, self.x)?;
+                } else {
+                    anyhow::bail!("invalid operand in Stx");
+                }
+                self.advance();
+            }
+            Sty(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.write_memory(addr, self.y)?;
+                } else {
+                    anyhow::bail!("invalid operaand in Sty");
+                }
+                self.advance();
+            }
+            Skip(mode) => {
+                self.get_operand_value(mode)?;
+                self.advance();
+            }
+            Lax(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(val);
+                self.x = val;
+                self.advance();
+            }
+            Sax(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.write_memory(addr, self.acc & self.x)?;
+                } else {
+                    anyhow::bail!("invalid operand in Sax");
+                }
+                self.advance();
+            }
+            Dcp(mode) => {
+                let val = self.read_modify_write(mode, |_, v| v.wrapping_sub(1))?;
+                self.compare(self.acc, val);
+                self.advance();
+            }
+            Isc(mode) => {
+                let val = self.read_modify_write(mode, |_, v| v.wrapping_add(1))?;
+                self.sbc(val);
+                self.advance();
+            }
+            Slo(mode) => {
+                let val = self.read_modify_write(mode, |m, v| m.shift_left(v, 0))?;
+                self.set_acc(self.acc | val);
+                self.advance();
+            }
+            Rla(mode) => {
+                let carry = self.carry_bit();
+                let val = self.read_modify_write(mode, |m, v| m.shift_left(v, carry))?;
+                self.set_acc(self.acc & val);
+                self.advance();
+            }
+            Sre(mode) => {
+                let val = self.read_modify_write(mode, |m, v| m.shift_right(v, 0))?;
+                self.set_acc(self.acc ^ val);
+                self.advance();
+            }
+            Rra(mode) => {
+                let carry = self.carry_bit();
+                let val = self.read_modify_write(mode, |m, v| m.shift_right(v, carry))?;
+                self.adc(val);
+                self.advance();
+            }
+            Anc(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc(self.acc & val);
+                if self.is_negative() {
+                    self.set_carry();
+                } else {
+                    self.cls_carry();
+                }
+                self.advance();
+            }
+            Alr(mode) => {
+                let val = self.get_operand_value(mode)?;
+                let result = self.shift_right(self.acc & val, 0);
+                self.set_acc(result);
+                self.advance();
+            }
+            Arr(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.arr(val);
+                self.advance();
+            }
+            Sbx(mode) => {
+                let val = self.get_operand_value(mode)?;
+                let and = self.acc & self.x;
+                self.compare(and, val);
+                self.set_x(and.wrapping_sub(val));
+                self.advance();
+            }
+            Las(mode) => {
+                let val = self.get_operand_value(mode)? & self.sp as u8;
+                self.set_acc(val);
+                self.x = val;
+                self.sp = val as usize;
+                self.advance();
+            }
+            Lxa(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc((self.acc | UNSTABLE_MAGIC) & val);
+                self.x = self.acc;
+                self.advance();
+            }
+            Xaa(mode) => {
+                let val = self.get_operand_value(mode)?;
+                self.set_acc((self.acc | UNSTABLE_MAGIC) & self.x & val);
+                self.advance();
+            }
+            Sha(mode) => {
+                self.store_unstable(mode, self.acc & self.x)?;
+                self.advance();
+            }
+            Shx(mode) => {
+                self.store_unstable(mode, self.x)?;
+                self.advance();
+            }
+            Shy(mode) => {
+                self.store_unstable(mode, self.y)?;
+                self.advance();
+            }
+            Tas(mode) => {
+                self.sp = (self.acc & self.x) as usize;
+                self.store_unstable(mode, self.acc & self.x)?;
+                self.advance();
+            }
+            Bra(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.branch(addr)?;
+                } else {
+                    anyhow::bail!("invalid address in Bra");
+                }
+            }
+            Phx => {
+                self.stack_push(self.x)?;
+                self.advance();
+            }
+            Phy => {
+                self.stack_push(self.y)?;
+                self.advance();
+            }
+            Plx => {
+                let val = self.stack_pop()?;
+                self.set_x(val);
+                self.advance();
+            }
+            Ply => {
+                let val = self.stack_pop()?;
+                self.set_y(val);
+                self.advance();
+            }
+            Stz(mode) => {
+                if let Address(addr) = self.get_operand(mode)? {
+                    self.write_memory(addr, 0)?;
+                } else {
+                    anyhow::bail!("invalid operand in Stz");
+                }
+                self.advance();
+            }
+            Trb(mode) => {
+                let acc = self.acc;
+                self.read_modify_write(mode, |m, v| {
+                    m.test_bits(acc, v);
+                    v & !acc
+                })?;
+                self.advance();
+            }
+            Tsb(mode) => {
+                let acc = self.acc;
+                self.read_modify_write(mode, |m, v| {
+                    m.test_bits(acc, v);
+                    v | acc
+                })?;
+                self.advance();
+            }
+            Rmb(bit, mode) => {
+                self.read_modify_write(mode, |_, v| v & !(1 << bit))?;
+                self.advance();
+            }
+            Smb(bit, mode) => {
+                self.read_modify_write(mode, |_, v| v | (1 << bit))?;
+                self.advance();
+            }
+            Bbr(bit, mode) => self.branch_on_bit(bit, mode, false)?,
+            Bbs(bit, mode) => self.branch_on_bit(bit, mode, true)?,
+            Wai => {
+                self.waiting = true;
+                self.advance();
+            }
+            Stp | Halt => {
+                // stay on the opcode so that stepping again doesn't run what follows
+                self.pc = self.bpc;
+                return Ok(Status::Halt);
+            }
+        };
+        Ok(Status::Cont)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Halt,
+    Cont,
+}
+
+impl<B: Bus> Iterator for Machine<B> {
+    type Item = u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        // instruction fetches bypass the read watchpoints
+        let pc = self.pc;
+        self.pc = (pc + 1) % ADDRESS_SPACE;
+        Some(self.bus.read(pc as u16))
+    }
+}
+
+#[inline]
+fn same_page(a: usize, b: usize) -> bool {
+    a & 0xFF00 == b & 0xFF00
+}
+
+fn sign_bit(b: u8) -> u8 {
+    (b & BIT7) >> 7
+}
+
+fn is_negative(b: u8) -> bool {
+    sign_bit(b) == 1
+}
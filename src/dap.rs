@@ -0,0 +1,936 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use log::{debug, trace};
+use serde_json::{Value, json};
+
+use crate::{
+    Cpu, Flags, Machine, Registers, Status,
+    callstack::above,
+    expr::Expr,
+    gdb::{checksum_of, escape, hex, parse_hex_bytes, unescape},
+    opcodes, parse_opcode,
+    symbols::Symbols,
+};
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REF: i64 = 1;
+const FLAGS_REF: i64 = 2;
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+/// Steps run between two looks at the incoming requests for a pause
+const POLL_STEPS: u32 = 4096;
+
+const FLAGS: [(&str, Flags); 6] = [
+    ("N", Flags::NEGATIVE),
+    ("V", Flags::OVERFLOW),
+    ("D", Flags::DECIMAL),
+    ("I", Flags::INTERRUPT_DISABLE),
+    ("Z", Flags::ZERO),
+    ("C", Flags::CARRY),
+];
+
+/// Why a target stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Step,
+    Breakpoint,
+    Pause,
+    Halt,
+}
+
+/// A machine the adapter can drive, in this process or behind a GDB stub
+trait Target {
+    fn cpu(&self) -> Cpu;
+    fn symbols(&self) -> &Symbols;
+    fn registers(&mut self) -> anyhow::Result<Registers>;
+    fn set_registers(&mut self, registers: Registers) -> anyhow::Result<()>;
+    fn read_memory(&mut self, addr: u16, len: usize) -> anyhow::Result<Vec<u8>>;
+    fn write_memory(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()>;
+    fn step(&mut self) -> anyhow::Result<Stop>;
+    /// Run until a breakpoint, a halt or `pause` returning true
+    fn resume(
+        &mut self,
+        breakpoints: &BTreeSet<u16>,
+        pause: &mut dyn FnMut() -> bool,
+    ) -> anyhow::Result<Stop>;
+    fn evaluate(&mut self, _expr: &Expr) -> anyhow::Result<i64> {
+        bail!("expressions are only evaluated in launched sessions")
+    }
+    fn detach(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+struct Local {
+    machine: Machine,
+}
+
+impl Target for Local {
+    fn cpu(&self) -> Cpu {
+        self.machine.cpu()
+    }
+
+    fn symbols(&self) -> &Symbols {
+        self.machine.symbols()
+    }
+
+    fn registers(&mut self) -> anyhow::Result<Registers> {
+        Ok(self.machine.registers())
+    }
+
+    fn set_registers(&mut self, registers: Registers) -> anyhow::Result<()> {
+        self.machine.set_registers(registers);
+        Ok(())
+    }
+
+    fn read_memory(&mut self, addr: u16, len: usize) -> anyhow::Result<Vec<u8>> {
+        Ok((0..len)
+            .map(|i| self.machine.peek(addr.wrapping_add(i as u16)))
+            .collect())
+    }
+
+    fn write_memory(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()> {
+        for (i, &byte) in data.iter().enumerate() {
+            self.machine.poke(addr.wrapping_add(i as u16), byte);
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> anyhow::Result<Stop> {
+        Ok(match self.machine.step()? {
+            Status::Halt => Stop::Halt,
+            Status::Cont => Stop::Step,
+        })
+    }
+
+    fn resume(
+        &mut self,
+        breakpoints: &BTreeSet<u16>,
+        pause: &mut dyn FnMut() -> bool,
+    ) -> anyhow::Result<Stop> {
+        let mut steps = 0;
+        loop {
+            if let Status::Halt = self.machine.step()? {
+                return Ok(Stop::Halt);
+            }
+            if breakpoints.contains(&self.machine.registers().pc) {
+                return Ok(Stop::Breakpoint);
+            }
+            steps += 1;
+            if steps == POLL_STEPS {
+                steps = 0;
+                if pause() {
+                    return Ok(Stop::Pause);
+                }
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> anyhow::Result<i64> {
+        expr.eval(&self.machine)
+    }
+}
+
+/// An emulator started with `--gdb`, driven through the remote serial protocol
+struct Remote {
+    stream: TcpStream,
+    cpu: Cpu,
+    symbols: Symbols,
+    received: Vec<u8>,
+    no_ack: bool,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Remote {
+    fn connect(host: &str, port: u16, cpu: Cpu, symbols: Symbols) -> anyhow::Result<Remote> {
+        let stream = TcpStream::connect((host, port))
+            .with_context(|| format!("connecting to the gdb stub on {host}:{port}"))?;
+        stream.set_nodelay(true)?;
+        // short timeouts let a continue look for a pause request
+        stream.set_read_timeout(Some(Duration::from_millis(50)))?;
+        let mut remote = Remote {
+            stream,
+            cpu,
+            symbols,
+            received: Vec::new(),
+            no_ack: false,
+            breakpoints: BTreeSet::new(),
+        };
+        remote.no_ack = remote.request("QStartNoAckMode")? == "OK";
+        Ok(remote)
+    }
+
+    fn send(&mut self, data: &str) -> anyhow::Result<()> {
+        let data = escape(data.as_bytes());
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&data);
+        write!(packet, "#{:02x}", checksum_of(&data))?;
+        self.stream.write_all(&packet)?;
+        Ok(())
+    }
+
+    fn request(&mut self, data: &str) -> anyhow::Result<String> {
+        self.send(data)?;
+        self.reply(&mut || false)
+    }
+
+    /// Wait for the next packet, sending a ^C the first time `pause` returns true
+    fn reply(&mut self, pause: &mut dyn FnMut() -> bool) -> anyhow::Result<String> {
+        let mut interrupted = false;
+        loop {
+            if let Some(packet) = self.take_packet()? {
+                return Ok(packet);
+            }
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer) {
+                Ok(0) => bail!("the gdb stub closed the connection"),
+                Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if !interrupted && pause() {
+                        self.stream.write_all(&[0x03])?;
+                        interrupted = true;
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn take_packet(&mut self) -> anyhow::Result<Option<String>> {
+        let Some(start) = self.received.iter().position(|&b| b == b'$') else {
+            self.received.clear();
+            return Ok(None);
+        };
+        let Some(end) = self.received[start..].iter().position(|&b| b == b'#') else {
+            return Ok(None);
+        };
+        let end = start + end;
+        if self.received.len() < end + 3 {
+            return Ok(None);
+        }
+        let packet = unescape(&self.received[start + 1..end]);
+        self.received.drain(..end + 3);
+        if !self.no_ack {
+            self.stream.write_all(b"+")?;
+        }
+        Ok(Some(String::from_utf8_lossy(&packet).into_owned()))
+    }
+
+    fn expect_ok(&mut self, data: &str) -> anyhow::Result<()> {
+        match self.request(data)?.as_str() {
+            "OK" => Ok(()),
+            reply => bail!("the gdb stub answered {reply} to {data}"),
+        }
+    }
+
+    /// The stub answers a continue with T05 on breakpoints and watchpoints, and with a
+    /// bare S05 when the processor halted
+    fn stop(reply: &str) -> anyhow::Result<Stop> {
+        Ok(match reply.get(..3) {
+            Some("S02" | "T02") => Stop::Pause,
+            Some("T05") => Stop::Breakpoint,
+            Some("S05") => Stop::Step,
+            Some("S04") => Stop::Halt,
+            _ if reply.starts_with(['W', 'X']) => Stop::Halt,
+            _ => bail!("unexpected stop reply {reply}"),
+        })
+    }
+}
+
+impl Target for Remote {
+    fn cpu(&self) -> Cpu {
+        self.cpu
+    }
+
+    fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    fn registers(&mut self) -> anyhow::Result<Registers> {
+        let reply = self.request("g")?;
+        match parse_hex_bytes(&reply).as_deref() {
+            Some(&[acc, x, y, sp, lo, hi, p]) => Ok(Registers {
+                acc,
+                x,
+                y,
+                sp,
+                pc: u16::from_le_bytes([lo, hi]),
+                flags: Flags::from_bits_retain(p),
+            }),
+            _ => bail!("unexpected registers {reply}"),
+        }
+    }
+
+    fn set_registers(&mut self, r: Registers) -> anyhow::Result<()> {
+        let [lo, hi] = r.pc.to_le_bytes();
+        let bytes = [r.acc, r.x, r.y, r.sp, lo, hi, r.flags.bits()];
+        self.expect_ok(&format!("G{}", hex(&bytes)))
+    }
+
+    fn read_memory(&mut self, addr: u16, len: usize) -> anyhow::Result<Vec<u8>> {
+        let reply = self.request(&format!("m{addr:x},{len:x}"))?;
+        parse_hex_bytes(&reply).with_context(|| format!("unexpected memory {reply}"))
+    }
+
+    fn write_memory(&mut self, addr: u16, data: &[u8]) -> anyhow::Result<()> {
+        self.expect_ok(&format!("M{addr:x},{:x}:{}", data.len(), hex(data)))
+    }
+
+    fn step(&mut self) -> anyhow::Result<Stop> {
+        let reply = self.request("s")?;
+        Remote::stop(&reply)
+    }
+
+    fn resume(
+        &mut self,
+        breakpoints: &BTreeSet<u16>,
+        pause: &mut dyn FnMut() -> bool,
+    ) -> anyhow::Result<Stop> {
+        for &addr in self.breakpoints.clone().difference(breakpoints) {
+            self.expect_ok(&format!("z0,{addr:x},1"))?;
+        }
+        for &addr in breakpoints.difference(&self.breakpoints.clone()) {
+            self.expect_ok(&format!("Z0,{addr:x},1"))?;
+        }
+        self.breakpoints = breakpoints.clone();
+        self.send("c")?;
+        let reply = self.reply(pause)?;
+        Remote::stop(&reply)
+    }
+
+    fn detach(&mut self) -> anyhow::Result<()> {
+        self.expect_ok("D")
+    }
+}
+
+/// Serve the Debug Adapter Protocol on `input` and `output`, usually stdin and stdout.
+/// `launch` builds the machine from the arguments of a launch request, an attach
+/// request connects to an emulator started with `--gdb`.
+pub fn serve(
+    input: impl Read + Send + 'static,
+    output: impl Write,
+    launch: impl FnMut(&Value) -> anyhow::Result<Machine>,
+) -> anyhow::Result<()> {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    debug!("[dap] {e:#}");
+                    return;
+                }
+            }
+        }
+    });
+    let mut session = Session {
+        requests,
+        pending: VecDeque::new(),
+        output,
+        seq: 0,
+        launch,
+        target: None,
+        instruction_breakpoints: BTreeSet::new(),
+        function_breakpoints: BTreeSet::new(),
+        stop_on_entry: true,
+    };
+    session.run()
+}
+
+fn read_message(input: &mut impl BufRead) -> anyhow::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; length.context("missing Content-Length")?];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+struct Session<W, L> {
+    requests: Receiver<Value>,
+    /// Requests that arrived while the target was running
+    pending: VecDeque<Value>,
+    output: W,
+    seq: i64,
+    launch: L,
+    target: Option<Box<dyn Target>>,
+    instruction_breakpoints: BTreeSet<u16>,
+    function_breakpoints: BTreeSet<u16>,
+    stop_on_entry: bool,
+}
+
+impl<W: Write, L: FnMut(&Value) -> anyhow::Result<Machine>> Session<W, L> {
+    fn run(&mut self) -> anyhow::Result<()> {
+        loop {
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => match self.requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => return Ok(()),
+                },
+            };
+            let command = request["command"].as_str().unwrap_or_default().to_string();
+            trace!("[dap] <- {request}");
+            let result = self.handle(&command, &request);
+            let handled = result.is_ok();
+            self.respond(&request, result)?;
+            if !handled {
+                continue;
+            }
+            let stop = match command.as_str() {
+                // breakpoints are set once symbols are loaded with the program
+                "launch" | "attach" => {
+                    self.event("initialized", json!({}))?;
+                    continue;
+                }
+                "configurationDone" if self.target.is_some() => {
+                    if self.stop_on_entry {
+                        self.stopped("entry", None)?;
+                        continue;
+                    }
+                    self.resume()
+                }
+                "continue" => self.resume(),
+                "next" => self.step_over(),
+                "stepIn" => self.target().and_then(|target| target.step()),
+                "stepOut" => self.step_out(),
+                "disconnect" => {
+                    if let Some(target) = &mut self.target {
+                        target.detach()?;
+                    }
+                    return Ok(());
+                }
+                _ => continue,
+            };
+            match stop {
+                Ok(stop) => self.report(stop, "step")?,
+                // the session goes on, the client decides whether to disconnect
+                Err(error) => {
+                    let message = format!("{error:#}");
+                    let output = json!({ "category": "stderr", "output": format!("{message}\n") });
+                    self.event("output", output)?;
+                    self.stopped("exception", Some(&message))?;
+                }
+            }
+        }
+    }
+
+    fn target(&mut self) -> anyhow::Result<&mut dyn Target> {
+        match &mut self.target {
+            Some(target) => Ok(target.as_mut()),
+            None => bail!("no program launched or attached"),
+        }
+    }
+
+    /// The body of the response to a request, the execution requests only
+    /// acknowledge here and run afterwards
+    fn handle(&mut self, command: &str, request: &Value) -> anyhow::Result<Value> {
+        let args = &request["arguments"];
+        Ok(match command {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsDisassembleRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsSetVariable": true,
+                "supportsSteppingGranularity": true,
+                "supportsEvaluateForHovers": true,
+            }),
+            "launch" => {
+                let machine = (self.launch)(args)?;
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(true);
+                self.target = Some(Box::new(Local { machine }));
+                json!({})
+            }
+            "attach" => {
+                let port = args["port"].as_u64().context("attach needs a port")?;
+                let host = args["host"].as_str().unwrap_or("127.0.0.1");
+                let cpu = match args["cpu"].as_str() {
+                    Some(cpu) => cpu.parse()?,
+                    None => Cpu::Nmos,
+                };
+                let port = u16::try_from(port).context("bad port")?;
+                let mut symbols = Symbols::new();
+                for path in args["symbols"].as_array().into_iter().flatten() {
+                    symbols.load_file(path.as_str().context("symbols are file names")?)?;
+                }
+                let remote = Remote::connect(host, port, cpu, symbols)?;
+                self.target = Some(Box::new(remote));
+                self.stop_on_entry = true;
+                json!({})
+            }
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" => {
+                if command != "configurationDone" {
+                    self.target()?;
+                }
+                json!({ "allThreadsContinued": true })
+            }
+            "disconnect" | "pause" => json!({}),
+            "setBreakpoints" => {
+                let count = args["breakpoints"].as_array().map_or(0, Vec::len);
+                let unverified = json!({
+                    "verified": false,
+                    "message": "no source mapping, use instruction or function breakpoints",
+                });
+                json!({ "breakpoints": vec![unverified; count] })
+            }
+            "setInstructionBreakpoints" => {
+                let (set, body) = set_breakpoints(args, |b| {
+                    let addr = parse_reference(b["instructionReference"].as_str()?)?;
+                    let offset = b["offset"].as_i64().unwrap_or(0);
+                    Some((addr as i64 + offset) as u16)
+                });
+                self.instruction_breakpoints = set;
+                body
+            }
+            "setFunctionBreakpoints" => {
+                let symbols = self.target()?.symbols();
+                let (set, body) =
+                    set_breakpoints(args, |b| symbols.resolve(b["name"].as_str()?).ok());
+                self.function_breakpoints = set;
+                body
+            }
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] }),
+            "stackTrace" => {
+                let pc = self.target()?.registers()?.pc;
+                let (text, _) = self.disassemble_at(pc)?;
+                let name = match self.target()?.symbols().label(pc) {
+                    Some(label) => format!("{label}: {text}"),
+                    None => text,
+                };
+                json!({
+                    "stackFrames": [{
+                        "id": 1,
+                        "name": name,
+                        "line": 0,
+                        "column": 0,
+                        "instructionPointerReference": format!("0x{pc:04x}"),
+                    }],
+                    "totalFrames": 1,
+                })
+            }
+            "scopes" => json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false },
+                    { "name": "Flags", "variablesReference": FLAGS_REF, "expensive": false },
+                ]
+            }),
+            "variables" => {
+                let r = self.target()?.registers()?;
+                let variables: Vec<Value> = match args["variablesReference"].as_i64() {
+                    Some(REGISTERS_REF) => registers(&r)
+                        .into_iter()
+                        .map(|(name, value, width)| variable(name, format_value(value, width)))
+                        .collect(),
+                    Some(FLAGS_REF) => FLAGS
+                        .iter()
+                        .map(|&(name, flag)| {
+                            variable(name, (r.flags.contains(flag) as u8).to_string())
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                json!({ "variables": variables })
+            }
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let value = args["value"].as_str().unwrap_or_default();
+                let value = parse_number(value).with_context(|| format!("bad value {value}"))?;
+                let mut r = self.target()?.registers()?;
+                let shown = match args["variablesReference"].as_i64() {
+                    Some(REGISTERS_REF) => {
+                        let width = set_register(&mut r, name, value)?;
+                        format_value(value, width)
+                    }
+                    Some(FLAGS_REF) => {
+                        let (_, flag) = FLAGS
+                            .iter()
+                            .find(|(flag, _)| *flag == name)
+                            .with_context(|| format!("unknown flag {name}"))?;
+                        r.flags.set(*flag, value != 0);
+                        ((value != 0) as u8).to_string()
+                    }
+                    _ => bail!("unknown variable {name}"),
+                };
+                self.target()?.set_registers(r)?;
+                json!({ "value": shown })
+            }
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                let expr: Expr = expression.parse()?;
+                let value = self.target()?.evaluate(&expr)?;
+                json!({ "result": format!("{value} (${value:x})"), "variablesReference": 0 })
+            }
+            "readMemory" => {
+                let addr = memory_address(args)?;
+                let count = args["count"]
+                    .as_u64()
+                    .unwrap_or(0)
+                    .min(0x10000 - addr as u64);
+                let data = self.target()?.read_memory(addr, count as usize)?;
+                json!({ "address": format!("0x{addr:04x}"), "data": BASE64.encode(data) })
+            }
+            "writeMemory" => {
+                let addr = memory_address(args)?;
+                let data = BASE64
+                    .decode(args["data"].as_str().unwrap_or_default())
+                    .context("bad base64 data")?;
+                self.target()?.write_memory(addr, &data)?;
+                json!({ "bytesWritten": data.len() })
+            }
+            "disassemble" => {
+                let addr = memory_address(args)?;
+                let offset = args["instructionOffset"].as_i64().unwrap_or(0);
+                let count = args["instructionCount"].as_u64().unwrap_or(0) as usize;
+                json!({ "instructions": self.disassemble(addr, offset, count)? })
+            }
+            _ => bail!("unsupported request {command}"),
+        })
+    }
+
+    fn breakpoints(&self) -> BTreeSet<u16> {
+        self.instruction_breakpoints
+            .union(&self.function_breakpoints)
+            .copied()
+            .collect()
+    }
+
+    /// One line of disassembly and the length of the instruction
+    fn disassemble_at(&mut self, addr: u16) -> anyhow::Result<(String, u16)> {
+        let target = self.target()?;
+        let cpu = target.cpu();
+        let bytes = target.read_memory(addr, 3)?;
+        let length = opcodes(cpu)[bytes[0] as usize].length as u16;
+        let mut cursor = bytes.iter().take(length as usize).copied();
+        Ok(match parse_opcode(&mut cursor, cpu, true) {
+            Ok(Some(op)) => (target.symbols().operation(&op, addr), length),
+            Ok(None) => ("HALT".to_string(), length),
+            Err(_) => ("???".to_string(), 1),
+        })
+    }
+
+    fn disassemble(&mut self, addr: u16, offset: i64, count: usize) -> anyhow::Result<Vec<Value>> {
+        let mut instructions = Vec::new();
+        let mut cursor = addr as i64;
+        if offset < 0 {
+            // instructions are up to 3 bytes long, decode forward from far enough back
+            let mut earlier = Vec::new();
+            let mut back = (addr as i64 + offset * 3).max(0);
+            while back < addr as i64 {
+                let (text, length) = self.disassemble_at(back as u16)?;
+                earlier.push((back, text, length));
+                back += length as i64;
+            }
+            // one running into `addr` is dropped, so that `addr` starts an instruction
+            if back > addr as i64 {
+                earlier.pop();
+            }
+            let wanted = offset.unsigned_abs() as usize;
+            for _ in earlier.len()..wanted {
+                instructions.push(invalid(0));
+            }
+            let skip = earlier.len().saturating_sub(wanted);
+            for (at, text, length) in earlier.into_iter().skip(skip) {
+                instructions.push(self.instruction(at as u16, text, length)?);
+            }
+        } else {
+            for _ in 0..offset {
+                cursor += self.disassemble_at(cursor as u16)?.1 as i64;
+            }
+        }
+        while instructions.len() < count {
+            if cursor > 0xFFFF {
+                instructions.push(invalid(0xFFFF));
+                continue;
+            }
+            let (text, length) = self.disassemble_at(cursor as u16)?;
+            instructions.push(self.instruction(cursor as u16, text, length)?);
+            cursor += length as i64;
+        }
+        instructions.truncate(count);
+        Ok(instructions)
+    }
+
+    fn instruction(&mut self, addr: u16, text: String, length: u16) -> anyhow::Result<Value> {
+        let target = self.target()?;
+        let bytes = target.read_memory(addr, length as usize)?;
+        let bytes: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        let mut instruction = json!({
+            "address": format!("0x{addr:04x}"),
+            "instructionBytes": bytes.join(" "),
+            "instruction": text,
+        });
+        if let Some(name) = target.symbols().name(addr) {
+            instruction["symbol"] = json!(name);
+        }
+        Ok(instruction)
+    }
+
+    fn resume(&mut self) -> anyhow::Result<Stop> {
+        let breakpoints = self.breakpoints();
+        self.run_target(|target, pause| target.resume(&breakpoints, pause))
+    }
+
+    /// Run the target, answering the pause requests coming in meanwhile
+    fn run_target(
+        &mut self,
+        run: impl FnOnce(&mut dyn Target, &mut dyn FnMut() -> bool) -> anyhow::Result<Stop>,
+    ) -> anyhow::Result<Stop> {
+        let requests = &self.requests;
+        let pending = &mut self.pending;
+        let mut pauses = Vec::new();
+        // other requests wait for the stop, a disconnect stops the target first
+        let mut pause = || {
+            while let Ok(request) = requests.try_recv() {
+                match request["command"].as_str() {
+                    Some("pause") => {
+                        pauses.push(request);
+                        return true;
+                    }
+                    Some("disconnect") => {
+                        pending.push_back(request);
+                        return true;
+                    }
+                    _ => pending.push_back(request),
+                }
+            }
+            false
+        };
+        let target = match &mut self.target {
+            Some(target) => target.as_mut(),
+            None => bail!("no program launched or attached"),
+        };
+        let stop = run(target, &mut pause)?;
+        for request in pauses {
+            self.respond(&request, Ok(json!({})))?;
+        }
+        Ok(stop)
+    }
+
+    /// Run a JSR as one step
+    fn step_over(&mut self) -> anyhow::Result<Stop> {
+        let target = self.target()?;
+        let r = target.registers()?;
+        if target.read_memory(r.pc, 1)?[0] != JSR {
+            return target.step();
+        }
+        let ret = r.pc.wrapping_add(3);
+        let mut breakpoints = self.breakpoints();
+        breakpoints.insert(ret);
+        loop {
+            let stop = self.run_target(|target, pause| target.resume(&breakpoints, pause))?;
+            let now = self.target()?.registers()?;
+            if stop != Stop::Breakpoint || now.pc != ret || now.sp == r.sp {
+                return Ok(match stop {
+                    Stop::Breakpoint if now.pc == ret => Stop::Step,
+                    stop => stop,
+                });
+            }
+            // a recursive call returned to the same address, keep going
+            if self.breakpoints().contains(&ret) {
+                return Ok(Stop::Breakpoint);
+            }
+        }
+    }
+
+    /// Step until the current subroutine returns
+    fn step_out(&mut self) -> anyhow::Result<Stop> {
+        let breakpoints = self.breakpoints();
+        self.run_target(|target, pause| {
+            let sp = target.registers()?.sp;
+            let mut steps = 0;
+            loop {
+                let pc = target.registers()?.pc;
+                let returning = matches!(target.read_memory(pc, 1)?[0], RTS | RTI);
+                let stop = target.step()?;
+                if stop != Stop::Step {
+                    return Ok(stop);
+                }
+                let now = target.registers()?;
+                if returning && above(now.sp, sp) {
+                    return Ok(Stop::Step);
+                }
+                if breakpoints.contains(&now.pc) {
+                    return Ok(Stop::Breakpoint);
+                }
+                steps += 1;
+                if steps % POLL_STEPS == 0 && pause() {
+                    return Ok(Stop::Pause);
+                }
+            }
+        })
+    }
+
+    fn report(&mut self, stop: Stop, step_reason: &str) -> anyhow::Result<()> {
+        match stop {
+            Stop::Step => self.stopped(step_reason, None),
+            Stop::Breakpoint => self.stopped("breakpoint", None),
+            Stop::Pause => self.stopped("pause", None),
+            Stop::Halt => self.stopped("exception", Some("the processor halted")),
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<&str>) -> anyhow::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+            body["description"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    fn respond(&mut self, request: &Value, result: anyhow::Result<Value>) -> anyhow::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+        });
+        match result {
+            Ok(body) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+            }
+            Err(e) => {
+                response["success"] = json!(false);
+                response["message"] = json!(format!("{e:#}"));
+            }
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> anyhow::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> anyhow::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        trace!("[dap] -> {message}");
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// The addresses of a set breakpoints request, with the verified breakpoints of the
+/// response. Each request replaces all the breakpoints of its kind.
+fn set_breakpoints(args: &Value, addr: impl Fn(&Value) -> Option<u16>) -> (BTreeSet<u16>, Value) {
+    let mut set = BTreeSet::new();
+    let breakpoints: Vec<Value> = args["breakpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|b| match addr(b) {
+            Some(addr) => {
+                set.insert(addr);
+                json!({ "verified": true, "instructionReference": format!("0x{addr:04x}") })
+            }
+            None => json!({ "verified": false, "message": "not an address" }),
+        })
+        .collect();
+    (set, json!({ "breakpoints": breakpoints }))
+}
+
+/// A placeholder for addresses outside of the address space
+fn invalid(addr: u16) -> Value {
+    json!({
+        "address": format!("0x{addr:04x}"),
+        "instruction": "",
+        "presentationHint": "invalid",
+    })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+/// The registers with their value and width in hex digits
+fn registers(r: &Registers) -> [(&'static str, u32, usize); 6] {
+    [
+        ("A", r.acc as u32, 2),
+        ("X", r.x as u32, 2),
+        ("Y", r.y as u32, 2),
+        ("SP", r.sp as u32, 2),
+        ("PC", r.pc as u32, 4),
+        ("P", (r.flags | Flags::UNUSED).bits() as u32, 2),
+    ]
+}
+
+fn format_value(value: u32, width: usize) -> String {
+    format!("${value:0width$x}")
+}
+
+/// Set a register by name, returns its width in hex digits
+fn set_register(r: &mut Registers, name: &str, value: u32) -> anyhow::Result<usize> {
+    let byte = || u8::try_from(value).with_context(|| format!("{value} is not a byte"));
+    match name {
+        "A" => r.acc = byte()?,
+        "X" => r.x = byte()?,
+        "Y" => r.y = byte()?,
+        "SP" => r.sp = byte()?,
+        "PC" => {
+            r.pc = u16::try_from(value).context("pc is 16 bits")?;
+            return Ok(4);
+        }
+        "P" => r.flags = Flags::from_bits_retain(byte()?),
+        _ => bail!("unknown register {name}"),
+    }
+    Ok(2)
+}
+
+/// A number as typed in the editor: decimal, or hex with a $ or 0x prefix
+fn parse_number(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// A memory or instruction reference, an address as `0x0600` or `$0600`
+fn parse_reference(s: &str) -> Option<u16> {
+    parse_number(s).and_then(|addr| u16::try_from(addr).ok())
+}
+
+fn memory_address(args: &Value) -> anyhow::Result<u16> {
+    let reference = args["memoryReference"].as_str().unwrap_or_default();
+    let addr =
+        parse_reference(reference).with_context(|| format!("bad memory reference {reference}"))?;
+    let addr = addr as i64 + args["offset"].as_i64().unwrap_or(0);
+    u16::try_from(addr).context("address out of range")
+}
@@ -0,0 +1,686 @@
+use std::{
+    io::{BufRead, Write},
+    ops::RangeInclusive,
+};
+
+use anyhow::{Context, bail};
+
+use crate::{
+    Bus, Flags, Machine, Operation, Registers, Status,
+    breakpoint::{BreakAction, Breakpoint, Breakpoints},
+    callstack::{FrameKind, above},
+    history::Undo,
+    opcodes, parse_opcode, savestate,
+    symbols::Symbols,
+    watch::{Access, WatchAction, Watchpoint},
+};
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+/// Steps kept by `record` without an argument
+pub const DEFAULT_HISTORY: usize = 10_000;
+
+const HELP: &str = "\
+s, step [n]            execute n instructions (1)
+n, next                step over a JSR
+finish                 run until the current subroutine returns
+c, continue            run until a breakpoint or a halt
+u, until <addr>        run until pc reaches addr
+b, break <addr> [if <expr>]
+b, break if <expr>     set a breakpoint, optionally conditional
+delete <n>             remove a breakpoint
+ignore <n> <count>     pass over the next count hits of a breakpoint
+action <n> pause|log   stop at a breakpoint or only log its hits
+breaks                 list the breakpoints
+w, watch <addr>[-<end>] [read|write|change] [log]
+                       stop (or only log) on an access, write by default
+unwatch <n>            remove a watchpoint
+watches                list the watchpoints
+record [n|off]         keep the last n steps (10000) to step back through
+bs, back [n]           undo n steps (1)
+rc, rcontinue          run backwards to the previous breakpoint
+rwatch [<addr>[-<end>]]
+                       run backwards to the last write to addr, or to a watchpoint
+save <file>            write a save state
+restore <file>         load a save state
+bt, backtrace          show the calls and interrupts pc is in, with their stack bytes
+profile [on|off]       count cycles per address and routine, or show the counts
+profile stacks <file>  write the counts per chain of calls for flamegraph tools
+r, regs                show the registers and flags
+set <reg> <value>      set a, x, y, sp, pc, p or a flag n, v, d, i, z, c
+x <addr> [len]         examine memory (16 bytes)
+poke <addr> <byte>...  write bytes to memory
+fill <start> <end> <byte>
+d, dis [addr] [n]      disassemble n instructions from addr (pc, 8)
+symbols <file>         load ca65 .dbg, VICE label or name = $addr symbols
+q, quit                leave the debugger
+Numbers are hex, optionally prefixed with $ or 0x, addresses can also be symbols
+like updateSnake or updateSnake+3. In expressions they are decimal
+unless prefixed, and a x y sp pc p, the flags n v d i z c, cycles, mem[addr] and
+word[addr] can be combined with the C operators";
+
+/// Whether the REPL keeps reading commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Why execution handed control back to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Step,
+    /// The breakpoint with this index acted with the pause action
+    Breakpoint(usize),
+    /// A watchpoint with the pause action triggered
+    Watch,
+    Halt,
+}
+
+/// An interactive debugger driving a [`Machine`] one command at a time
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Breakpoints,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    /// Evaluate the breakpoints before the instruction at pc, printing the logging
+    /// ones, returns the first one asking to pause
+    pub fn check_breakpoints<B: Bus>(
+        &mut self,
+        machine: &Machine<B>,
+        out: &mut impl Write,
+    ) -> anyhow::Result<Option<usize>> {
+        let mut pause = None;
+        for hit in self.breakpoints.check(machine)? {
+            match hit.action {
+                BreakAction::Log => writeln!(out, "{hit}: {}", machine.registers())?,
+                BreakAction::Pause => pause = pause.or(Some(hit.index)),
+            }
+        }
+        Ok(pause)
+    }
+
+    /// Read commands until `quit` or the end of the input
+    pub fn repl<B: Bus>(
+        &mut self,
+        machine: &mut Machine<B>,
+        input: impl BufRead,
+        out: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        self.show_pc(machine, out)?;
+        write!(out, "(b6502) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            match self.command(machine, &line?, out) {
+                Ok(Flow::Quit) => return Ok(()),
+                Ok(Flow::Continue) => {}
+                Err(e) => writeln!(out, "error: {e:#}")?,
+            }
+            write!(out, "(b6502) ")?;
+            out.flush()?;
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Run one command line
+    pub fn command<B: Bus>(
+        &mut self,
+        machine: &mut Machine<B>,
+        line: &str,
+        out: &mut impl Write,
+    ) -> anyhow::Result<Flow> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(Flow::Continue);
+        };
+        let args: Vec<&str> = words.collect();
+        match command {
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => parse_number(n)?,
+                    None => 1,
+                };
+                let mut left = count;
+                let stop = if count == 0 {
+                    Stop::Step
+                } else {
+                    self.run_until(machine, out, |_| {
+                        left -= 1;
+                        left == 0
+                    })?
+                };
+                self.report(machine, stop, out)?;
+            }
+            "n" | "next" => {
+                let pc = machine.registers().pc;
+                let stop = if machine.peek(pc) == JSR {
+                    let sp = machine.registers().sp;
+                    self.run_until(machine, out, |m| {
+                        let r = m.registers();
+                        r.pc == pc.wrapping_add(3) && r.sp == sp
+                    })?
+                } else {
+                    self.run_until(machine, out, |_| true)?
+                };
+                self.report(machine, stop, out)?;
+            }
+            "finish" => {
+                let sp = machine.registers().sp;
+                let mut returning = false;
+                let stop = self.run_until_with(machine, out, |m, stepped| {
+                    if stepped {
+                        // the return popped the frame the subroutine was called with
+                        let done = returning && above(m.registers().sp, sp);
+                        returning = false;
+                        return done;
+                    }
+                    returning = matches!(m.peek(m.registers().pc), RTS | RTI);
+                    false
+                })?;
+                self.report(machine, stop, out)?;
+            }
+            "c" | "continue" => {
+                let stop = self.run_until(machine, out, |_| false)?;
+                self.report(machine, stop, out)?;
+            }
+            "u" | "until" => {
+                let addr = args.first().context("until needs an address")?;
+                let addr = machine.symbols().resolve(addr)?;
+                let stop = self.run_until(machine, out, |m| m.registers().pc == addr)?;
+                self.report(machine, stop, out)?;
+            }
+            "b" | "break" => {
+                let (addr, condition) = match args[..] {
+                    [] => bail!("break needs an address or a condition"),
+                    ["if", ref condition @ ..] => (None, condition),
+                    [addr, "if", ref condition @ ..] => {
+                        (Some(machine.symbols().resolve(addr)?), condition)
+                    }
+                    [addr] => (Some(machine.symbols().resolve(addr)?), &[][..]),
+                    _ => bail!("usage: break <addr> [if <expr>]"),
+                };
+                let condition = match condition {
+                    [] => None,
+                    condition => Some(condition.join(" ").parse()?),
+                };
+                let breakpoint = Breakpoint::new(addr, condition);
+                let index = self.breakpoints.add(breakpoint.clone());
+                writeln!(out, "breakpoint {index}: {breakpoint}")?;
+            }
+            "delete" => {
+                let index = parse_index(args.first())?;
+                if self.breakpoints.remove(index).is_none() {
+                    bail!("no breakpoint {index}");
+                }
+            }
+            "ignore" => {
+                let index = parse_index(args.first())?;
+                let count = args.get(1).context("usage: ignore <n> <count>")?;
+                let count = count
+                    .parse()
+                    .with_context(|| format!("bad number {count}"))?;
+                let breakpoint = self.breakpoints.get_mut(index);
+                breakpoint
+                    .with_context(|| format!("no breakpoint {index}"))?
+                    .ignore = count;
+            }
+            "action" => {
+                let index = parse_index(args.first())?;
+                let action = match args.get(1) {
+                    Some(&"pause") => BreakAction::Pause,
+                    Some(&"log") => BreakAction::Log,
+                    _ => bail!("usage: action <n> pause|log"),
+                };
+                let breakpoint = self.breakpoints.get_mut(index);
+                breakpoint
+                    .with_context(|| format!("no breakpoint {index}"))?
+                    .action = action;
+            }
+            "breaks" => {
+                for (index, breakpoint) in self.breakpoints.list() {
+                    writeln!(out, "breakpoint {index}: {breakpoint}")?;
+                }
+            }
+            "w" | "watch" => {
+                let range = parse_range(
+                    machine.symbols(),
+                    args.first().context("watch needs an address")?,
+                )?;
+                let mut access = Access::Write;
+                let mut action = WatchAction::Pause;
+                for arg in &args[1..] {
+                    match *arg {
+                        "log" => action = WatchAction::Log,
+                        arg => access = arg.parse()?,
+                    }
+                }
+                let watchpoint = Watchpoint {
+                    range,
+                    access,
+                    action,
+                };
+                let index = machine.watchpoints_mut().add(watchpoint.clone());
+                writeln!(out, "watch {index}: {watchpoint}")?;
+            }
+            "unwatch" => {
+                let index = parse_index(args.first())?;
+                if machine.watchpoints_mut().remove(index).is_none() {
+                    bail!("no watchpoint {index}");
+                }
+            }
+            "watches" => {
+                for (index, watchpoint) in machine.watchpoints().list() {
+                    writeln!(out, "watch {index}: {watchpoint}")?;
+                }
+            }
+            "record" => match args.first() {
+                Some(&"off") => machine.record_history(0),
+                steps => {
+                    let steps = match steps {
+                        Some(steps) => steps
+                            .parse()
+                            .with_context(|| format!("bad number {steps}"))?,
+                        None => DEFAULT_HISTORY,
+                    };
+                    machine.record_history(steps);
+                    writeln!(out, "recording the last {steps} steps")?;
+                }
+            },
+            "bs" | "back" => {
+                let mut left = match args.first() {
+                    Some(n) => parse_number(n)?.max(1),
+                    None => 1,
+                };
+                self.run_back(machine, out, |_, _| {
+                    left -= 1;
+                    Ok(left == 0)
+                })?;
+            }
+            "rc" | "rcontinue" => {
+                let breakpoints = &self.breakpoints;
+                self.run_back(machine, out, |m, _| Ok(breakpoints.pausing(m)?.is_some()))?;
+            }
+            "rwatch" => {
+                let ranges = match args.first() {
+                    Some(range) => vec![parse_range(machine.symbols(), range)?],
+                    None => machine
+                        .watchpoints()
+                        .list()
+                        .filter(|(_, w)| w.access != Access::Read)
+                        .map(|(_, w)| w.range.clone())
+                        .collect(),
+                };
+                if ranges.is_empty() {
+                    bail!("rwatch needs an address or a write watchpoint");
+                }
+                self.run_back(machine, out, |_, undo| {
+                    Ok(ranges.iter().any(|range| undo.wrote(range)))
+                })?;
+            }
+            "save" => {
+                let path = args.first().context("save needs a file name")?;
+                savestate::save_file(machine, path)?;
+            }
+            "restore" => {
+                let path = args.first().context("restore needs a file name")?;
+                savestate::load_file(machine, path)?;
+                self.show_pc(machine, out)?;
+            }
+            "bt" | "backtrace" => backtrace(machine, out)?,
+            "profile" => match args[..] {
+                ["on"] => {
+                    machine.record_profile(true);
+                    writeln!(out, "profiling")?;
+                }
+                ["off"] => machine.record_profile(false),
+                [] => {
+                    let profiler = machine
+                        .profiler()
+                        .context("not profiling, try profile on")?;
+                    profiler.report(machine, out)?;
+                }
+                ["stacks", path] => {
+                    let profiler = machine
+                        .profiler()
+                        .context("not profiling, try profile on")?;
+                    let mut file =
+                        std::fs::File::create(path).with_context(|| format!("creating {path}"))?;
+                    profiler.write_collapsed(machine.symbols(), &mut file)?;
+                }
+                _ => bail!("usage: profile [on|off|stacks <file>]"),
+            },
+            "r" | "regs" => show_registers(machine, out)?,
+            "set" => {
+                let [name, value] = args[..] else {
+                    bail!("usage: set <reg> <value>");
+                };
+                set_register(machine, name, parse_number(value)?)?;
+                show_registers(machine, out)?;
+            }
+            "x" => {
+                let start = args.first().context("x needs an address")?;
+                let start = machine.symbols().resolve(start)?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len)?.max(1),
+                    None => 16,
+                };
+                let end = start.saturating_add((len - 1).min(0xFFFF) as u16);
+                machine.dump_memory(start..=end, out)?;
+            }
+            "poke" => {
+                let (addr, bytes) = args.split_first().context("poke needs an address")?;
+                let addr = machine.symbols().resolve(addr)?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    machine.poke(addr.wrapping_add(i as u16), parse_byte(byte)?);
+                }
+            }
+            "fill" => {
+                let [start, end, byte] = args[..] else {
+                    bail!("usage: fill <start> <end> <byte>");
+                };
+                let byte = parse_byte(byte)?;
+                for addr in machine.symbols().resolve(start)?..=machine.symbols().resolve(end)? {
+                    machine.poke(addr, byte);
+                }
+            }
+            "d" | "dis" => {
+                let pc = machine.registers().pc;
+                let addr = match args.first() {
+                    Some(addr) => machine.symbols().resolve(addr)?,
+                    None => pc,
+                };
+                let count = match args.get(1) {
+                    Some(n) => parse_number(n)?,
+                    None => 8,
+                };
+                disassemble(machine, addr, count, out)?;
+            }
+            "symbols" => {
+                let path = args.first().context("symbols needs a file name")?;
+                let count = machine.symbols_mut().load_file(path)?;
+                writeln!(out, "{count} symbols")?;
+            }
+            "h" | "help" => writeln!(out, "{HELP}")?,
+            "q" | "quit" => return Ok(Flow::Quit),
+            _ => bail!("unknown command {command}, try help"),
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Step at least once, then until `done` holds, a breakpoint is reached or the machine halts
+    fn run_until<B: Bus>(
+        &mut self,
+        machine: &mut Machine<B>,
+        out: &mut impl Write,
+        mut done: impl FnMut(&Machine<B>) -> bool,
+    ) -> anyhow::Result<Stop> {
+        self.run_until_with(machine, out, |m, stepped| stepped && done(m))
+    }
+
+    /// Like `run_until`, `done` is also called before every step with `stepped` false
+    fn run_until_with<B: Bus>(
+        &mut self,
+        machine: &mut Machine<B>,
+        out: &mut impl Write,
+        mut done: impl FnMut(&Machine<B>, bool) -> bool,
+    ) -> anyhow::Result<Stop> {
+        loop {
+            done(machine, false);
+            let status = machine.step()?;
+            let mut paused = false;
+            for hit in machine.watchpoints_mut().take_hits() {
+                writeln!(out, "{hit}")?;
+                paused |= hit.action == WatchAction::Pause;
+            }
+            for divergence in machine.call_stack_mut().take_divergences() {
+                writeln!(out, "warning: {divergence}")?;
+            }
+            if let Status::Halt = status {
+                return Ok(Stop::Halt);
+            }
+            if let Some(index) = self.check_breakpoints(machine, out)? {
+                return Ok(Stop::Breakpoint(index));
+            }
+            if paused {
+                return Ok(Stop::Watch);
+            }
+            if done(machine, true) {
+                return Ok(Stop::Step);
+            }
+        }
+    }
+
+    /// Undo steps until `done` holds for the machine and the step just undone
+    fn run_back<B: Bus>(
+        &self,
+        machine: &mut Machine<B>,
+        out: &mut impl Write,
+        mut done: impl FnMut(&Machine<B>, &Undo) -> anyhow::Result<bool>,
+    ) -> anyhow::Result<()> {
+        if machine.history().is_none() {
+            bail!("not recording, use record first");
+        }
+        loop {
+            let Some(undo) = machine.step_back() else {
+                writeln!(out, "start of the recorded history")?;
+                break;
+            };
+            if done(machine, &undo)? {
+                break;
+            }
+        }
+        self.show_pc(machine, out)
+    }
+
+    fn report<B: Bus>(
+        &self,
+        machine: &Machine<B>,
+        stop: Stop,
+        out: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        match stop {
+            Stop::Step | Stop::Watch => {}
+            Stop::Breakpoint(index) => writeln!(out, "breakpoint {index}")?,
+            Stop::Halt => writeln!(out, "halted")?,
+        }
+        self.show_pc(machine, out)
+    }
+
+    fn show_pc<B: Bus>(&self, machine: &Machine<B>, out: &mut impl Write) -> anyhow::Result<()> {
+        disassemble(machine, machine.registers().pc, 1, out)?;
+        writeln!(out, "  {}", machine.registers())?;
+        Ok(())
+    }
+}
+
+/// Decode the instruction at `addr` without side effects
+pub fn decode<B: Bus>(machine: &Machine<B>, addr: u16) -> (Option<Operation>, u16) {
+    let length = opcodes(machine.cpu())[machine.peek(addr) as usize].length as u16;
+    let mut cursor = (0..length).map(|i| machine.peek(addr.wrapping_add(i)));
+    match parse_opcode(&mut cursor, machine.cpu(), true) {
+        Ok(op) => (op, length),
+        Err(_) => (None, 1),
+    }
+}
+
+fn disassemble<B: Bus>(
+    machine: &Machine<B>,
+    mut addr: u16,
+    count: usize,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let pc = machine.registers().pc;
+    for _ in 0..count {
+        let (op, length) = decode(machine, addr);
+        let bytes: Vec<String> = (0..length)
+            .map(|i| format!("{:0>2x}", machine.peek(addr.wrapping_add(i))))
+            .collect();
+        let marker = if addr == pc { '>' } else { ' ' };
+        if let Some(name) = machine.symbols().name(addr) {
+            writeln!(out, "{name}:")?;
+        }
+        match op {
+            Some(op) => {
+                let op = machine.symbols().operation(&op, addr);
+                writeln!(out, "{marker} {addr:0>4x}  {:<9} {op}", bytes.join(" "))?
+            }
+            None => writeln!(out, "{marker} {addr:0>4x}  {:<9} ???", bytes.join(" "))?,
+        }
+        addr = addr.wrapping_add(length);
+    }
+    Ok(())
+}
+
+/// One line per frame, innermost first. Each shows the page 1 bytes above its stack
+/// pointer up to the next frame: the return address, then what the caller pushed
+fn backtrace<B: Bus>(machine: &Machine<B>, out: &mut impl Write) -> anyhow::Result<()> {
+    let name = |addr: u16| match machine.symbols().label(addr) {
+        Some(label) => format!("{addr:04x} <{label}>"),
+        None => format!("{addr:04x}"),
+    };
+    let stack = |from: u8, to: u8| -> String {
+        if from >= to {
+            return String::new();
+        }
+        let bytes: Vec<String> = (from as u16 + 1..=to as u16)
+            .map(|sp| format!("{:02x}", machine.peek(0x100 + sp)))
+            .collect();
+        format!("  01{:02x}: {}", from.wrapping_add(1), bytes.join(" "))
+    };
+    let frames = machine.call_stack().frames();
+    let r = machine.registers();
+    let innermost = frames.last().map_or(0xFF, |frame| frame.sp);
+    writeln!(out, "#0  {}{}", name(r.pc), stack(r.sp, innermost))?;
+    if !frames.is_empty() && above(r.sp, innermost) {
+        writeln!(out, "warning: sp {:02x} is above the innermost frame", r.sp)?;
+    }
+    for (depth, frame) in frames.iter().rev().enumerate() {
+        let outer = frames
+            .len()
+            .checked_sub(depth + 2)
+            .map_or(0xFF, |i| frames[i].sp);
+        writeln!(
+            out,
+            "#{}  {}  {} {} -> {:04x}{}",
+            depth + 1,
+            name(frame.site),
+            frame.kind,
+            name(frame.target),
+            frame.ret,
+            stack(frame.sp, outer)
+        )?;
+        // JSR pushes the return address minus one, interrupts push it after P
+        let (addr, expected) = match frame.kind {
+            FrameKind::Jsr => (frame.sp.wrapping_add(1), frame.ret.wrapping_sub(1)),
+            _ => (frame.sp.wrapping_add(2), frame.ret),
+        };
+        let lo = machine.peek(0x100 + addr as u16);
+        let hi = machine.peek(0x100 + addr.wrapping_add(1) as u16);
+        let found = u16::from_le_bytes([lo, hi]);
+        if found != expected {
+            writeln!(
+                out,
+                "warning: the stack holds {found:04x} at 01{addr:02x}, expected {expected:04x}"
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn show_registers<B: Bus>(machine: &Machine<B>, out: &mut impl Write) -> anyhow::Result<()> {
+    let r = machine.registers();
+    let flags: String = [
+        (Flags::NEGATIVE, 'N'),
+        (Flags::OVERFLOW, 'V'),
+        (Flags::DECIMAL, 'D'),
+        (Flags::INTERRUPT_DISABLE, 'I'),
+        (Flags::ZERO, 'Z'),
+        (Flags::CARRY, 'C'),
+    ]
+    .iter()
+    .map(|&(flag, name)| if r.flags.contains(flag) { name } else { '.' })
+    .collect();
+    writeln!(
+        out,
+        "a:{:0>2x} x:{:0>2x} y:{:0>2x} sp:{:0>2x} pc:{:0>4x} flags:{} cycles:{}",
+        r.acc,
+        r.x,
+        r.y,
+        r.sp,
+        r.pc,
+        flags,
+        machine.cycles()
+    )?;
+    Ok(())
+}
+
+fn set_register<B: Bus>(machine: &mut Machine<B>, name: &str, value: usize) -> anyhow::Result<()> {
+    let mut r: Registers = machine.registers();
+    let byte = || u8::try_from(value).with_context(|| format!("{value:x} is not a byte"));
+    let flag = |flag: Flags| -> anyhow::Result<Flags> {
+        match value {
+            0 => Ok(r.flags - flag),
+            1 => Ok(r.flags | flag),
+            _ => bail!("a flag is 0 or 1"),
+        }
+    };
+    match name.to_ascii_lowercase().as_str() {
+        "a" => r.acc = byte()?,
+        "x" => r.x = byte()?,
+        "y" => r.y = byte()?,
+        "sp" => r.sp = byte()?,
+        "pc" => r.pc = u16::try_from(value).context("pc is 16 bits")?,
+        "p" => r.flags = Flags::from_bits_retain(byte()?),
+        "n" => r.flags = flag(Flags::NEGATIVE)?,
+        "v" => r.flags = flag(Flags::OVERFLOW)?,
+        "d" => r.flags = flag(Flags::DECIMAL)?,
+        "i" => r.flags = flag(Flags::INTERRUPT_DISABLE)?,
+        "z" => r.flags = flag(Flags::ZERO)?,
+        "c" => r.flags = flag(Flags::CARRY)?,
+        _ => bail!("unknown register {name}"),
+    }
+    machine.set_registers(r);
+    Ok(())
+}
+
+/// A hex number, optionally prefixed with $ or 0x
+fn parse_number(s: &str) -> anyhow::Result<usize> {
+    let digits = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    usize::from_str_radix(digits, 16).with_context(|| format!("bad number {s}"))
+}
+
+/// The number of a breakpoint or watchpoint, in decimal as listed
+fn parse_index(s: Option<&&str>) -> anyhow::Result<usize> {
+    let s = s.context("missing breakpoint or watchpoint number")?;
+    s.parse().with_context(|| format!("bad number {s}"))
+}
+
+/// An address or a start-end range of addresses, either can be a symbol
+fn parse_range(symbols: &Symbols, s: &str) -> anyhow::Result<RangeInclusive<u16>> {
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (symbols.resolve(start)?, symbols.resolve(end)?),
+        None => (symbols.resolve(s)?, symbols.resolve(s)?),
+    };
+    if start > end {
+        bail!("empty range {s}");
+    }
+    Ok(start..=end)
+}
+
+fn parse_byte(s: &str) -> anyhow::Result<u8> {
+    u8::try_from(parse_number(s)?).with_context(|| format!("{s} is not a byte"))
+}
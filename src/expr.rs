@@ -0,0 +1,339 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{Context, bail};
+
+use crate::{Bus, Flags, Machine};
+
+/// An expression over the machine state, e.g. `pc == $0638 && a == $77 && mem[$02] & 4`
+///
+/// Operands are numbers (decimal, or hex with a `$` or `0x` prefix), the registers
+/// `a x y sp pc p`, the flags `n v d i z c` (0 or 1), `cycles`, and the memory reads
+/// `mem[addr]` (a byte) and `word[addr]` (little endian). Other names are symbols of the
+/// machine, looked up when evaluated. The operators and their precedence follow C,
+/// comparisons and logic operators produce 0 or 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    Flag(Flags),
+    Cycles,
+    Symbol(String),
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    Pc,
+    P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Not,
+    Complement,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinaryOp {
+    fn from_token(token: &str) -> Option<(BinaryOp, u8)> {
+        use BinaryOp::*;
+        Some(match token {
+            "||" => (Or, 1),
+            "&&" => (And, 2),
+            "|" => (BitOr, 3),
+            "^" => (BitXor, 4),
+            "&" => (BitAnd, 5),
+            "==" => (Eq, 6),
+            "!=" => (Ne, 6),
+            "<" => (Lt, 7),
+            "<=" => (Le, 7),
+            ">" => (Gt, 7),
+            ">=" => (Ge, 7),
+            "<<" => (Shl, 8),
+            ">>" => (Shr, 8),
+            "+" => (Add, 9),
+            "-" => (Sub, 9),
+            "*" => (Mul, 10),
+            "/" => (Div, 10),
+            "%" => (Rem, 10),
+            _ => return None,
+        })
+    }
+
+    fn apply(self, lhs: i64, rhs: i64) -> anyhow::Result<i64> {
+        use BinaryOp::*;
+        Ok(match self {
+            Or => ((lhs != 0) || (rhs != 0)) as i64,
+            And => ((lhs != 0) && (rhs != 0)) as i64,
+            BitOr => lhs | rhs,
+            BitXor => lhs ^ rhs,
+            BitAnd => lhs & rhs,
+            Eq => (lhs == rhs) as i64,
+            Ne => (lhs != rhs) as i64,
+            Lt => (lhs < rhs) as i64,
+            Le => (lhs <= rhs) as i64,
+            Gt => (lhs > rhs) as i64,
+            Ge => (lhs >= rhs) as i64,
+            Shl => lhs.wrapping_shl(rhs as u32),
+            Shr => lhs.wrapping_shr(rhs as u32),
+            Add => lhs.wrapping_add(rhs),
+            Sub => lhs.wrapping_sub(rhs),
+            Mul => lhs.wrapping_mul(rhs),
+            Div | Rem if rhs == 0 => bail!("division by zero"),
+            Div => lhs.wrapping_div(rhs),
+            Rem => lhs.wrapping_rem(rhs),
+        })
+    }
+}
+
+const OPERATORS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+fn tokenize(s: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        let len = if c == '$' || c.is_ascii_digit() {
+            let (digits, radix, skip) = if let Some(hex) = rest.strip_prefix('$') {
+                (hex, 16, 1)
+            } else if let Some(hex) = rest.strip_prefix("0x") {
+                (hex, 16, 2)
+            } else {
+                (rest, 10, 0)
+            };
+            let len = digits
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(digits.len());
+            let value = i64::from_str_radix(&digits[..len], radix)
+                .with_context(|| format!("bad number {}", &rest[..skip + len]))?;
+            tokens.push(Token::Number(value));
+            skip + len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else {
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) else {
+                bail!("unexpected {c}");
+            };
+            tokens.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> anyhow::Result<()> {
+        match self.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            _ => bail!("expected {op}"),
+        }
+    }
+
+    /// Precedence climbing over the binary operators binding at least as tight as `min`
+    fn binary(&mut self, min: u8) -> anyhow::Result<Node> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Op(token)) = self.peek() {
+            let Some((op, precedence)) = BinaryOp::from_token(token) else {
+                break;
+            };
+            if precedence < min {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Node> {
+        let op = match self.peek() {
+            Some(Token::Op("!")) => UnaryOp::Not,
+            Some(Token::Op("~")) => UnaryOp::Complement,
+            Some(Token::Op("-")) => UnaryOp::Negate,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Node> {
+        Ok(match self.next().context("unexpected end of expression")? {
+            Token::Number(value) => Node::Number(value),
+            Token::Op("(") => {
+                let node = self.binary(0)?;
+                self.expect(")")?;
+                node
+            }
+            Token::Name(name) if matches!(name.to_ascii_lowercase().as_str(), "mem" | "word") => {
+                self.expect("[")?;
+                let addr = Box::new(self.binary(0)?);
+                self.expect("]")?;
+                if name.eq_ignore_ascii_case("mem") {
+                    Node::Byte(addr)
+                } else {
+                    Node::Word(addr)
+                }
+            }
+            Token::Name(name) => match name.to_ascii_lowercase().as_str() {
+                "a" => Node::Register(Register::A),
+                "x" => Node::Register(Register::X),
+                "y" => Node::Register(Register::Y),
+                "sp" => Node::Register(Register::Sp),
+                "pc" => Node::Register(Register::Pc),
+                "p" => Node::Register(Register::P),
+                "n" => Node::Flag(Flags::NEGATIVE),
+                "v" => Node::Flag(Flags::OVERFLOW),
+                "d" => Node::Flag(Flags::DECIMAL),
+                "i" => Node::Flag(Flags::INTERRUPT_DISABLE),
+                "z" => Node::Flag(Flags::ZERO),
+                "c" => Node::Flag(Flags::CARRY),
+                "cycles" => Node::Cycles,
+                _ => Node::Symbol(name),
+            },
+            Token::Op(op) => bail!("unexpected {op}"),
+        })
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let node = parser.binary(0)?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {token:?} in {s}");
+        }
+        Ok(Expr {
+            source: s.trim().to_string(),
+            node,
+        })
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(&self.source)
+    }
+}
+
+impl Expr {
+    pub fn eval<B: Bus>(&self, machine: &Machine<B>) -> anyhow::Result<i64> {
+        eval(&self.node, machine)
+    }
+
+    /// Evaluate as a condition, anything but 0 holds
+    pub fn holds<B: Bus>(&self, machine: &Machine<B>) -> anyhow::Result<bool> {
+        Ok(self.eval(machine)? != 0)
+    }
+}
+
+fn eval<B: Bus>(node: &Node, machine: &Machine<B>) -> anyhow::Result<i64> {
+    let registers = machine.registers();
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Register(register) => match register {
+            Register::A => registers.acc as i64,
+            Register::X => registers.x as i64,
+            Register::Y => registers.y as i64,
+            Register::Sp => registers.sp as i64,
+            Register::Pc => registers.pc as i64,
+            Register::P => (registers.flags | Flags::UNUSED).bits() as i64,
+        },
+        Node::Flag(flag) => registers.flags.contains(*flag) as i64,
+        Node::Cycles => machine.cycles() as i64,
+        Node::Symbol(name) => machine
+            .symbols()
+            .get(name)
+            .with_context(|| format!("unknown name {name}"))? as i64,
+        Node::Byte(addr) => machine.peek(eval(addr, machine)? as u16) as i64,
+        Node::Word(addr) => {
+            let addr = eval(addr, machine)? as u16;
+            u16::from_le_bytes([machine.peek(addr), machine.peek(addr.wrapping_add(1))]) as i64
+        }
+        Node::Unary(op, operand) => {
+            let value = eval(operand, machine)?;
+            match op {
+                UnaryOp::Not => (value == 0) as i64,
+                UnaryOp::Complement => !value,
+                UnaryOp::Negate => value.wrapping_neg(),
+            }
+        }
+        Node::Binary(BinaryOp::Or, lhs, rhs) => {
+            (eval(lhs, machine)? != 0 || eval(rhs, machine)? != 0) as i64
+        }
+        Node::Binary(BinaryOp::And, lhs, rhs) => {
+            (eval(lhs, machine)? != 0 && eval(rhs, machine)? != 0) as i64
+        }
+        Node::Binary(op, lhs, rhs) => op.apply(eval(lhs, machine)?, eval(rhs, machine)?)?,
+    })
+}
@@ -0,0 +1,213 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use log::trace;
+use sdl2::{
+    EventPump,
+    event::Event,
+    keyboard::Keycode,
+    pixels::{Color, PixelFormatEnum},
+    render::{Texture, WindowCanvas},
+};
+
+use b6502::{
+    Machine, Status,
+    bus::{Framebuffer, Keyboard},
+    debugger::Debugger,
+    savestate,
+    watch::WatchAction,
+};
+
+fn color(byte: u8) -> Color {
+    match byte {
+        0 => Color::BLACK,
+        1 => Color::WHITE,
+        2 | 9 => Color::GRAY,
+        3 | 10 => Color::RED,
+        4 | 11 => Color::GREEN,
+        5 | 12 => Color::BLUE,
+        6 | 13 => Color::MAGENTA,
+        7 | 14 => Color::YELLOW,
+        _ => Color::CYAN,
+    }
+}
+
+fn string_to_err(s: String) -> anyhow::Error {
+    anyhow::anyhow!(s)
+}
+
+/// Open the "Snake Game" window and run the machine until it halts or the window is closed.
+/// A breakpoint pausing opens the debugger on stdin, quitting it resumes the window.
+/// F5 saves the machine to `state`, F9 restores it.
+pub fn run(
+    machine: &mut Machine,
+    clk_micros: u64,
+    debugger: Debugger,
+    state: &Path,
+) -> anyhow::Result<()> {
+    let sdl_context = sdl2::init().map_err(string_to_err)?;
+    let video_subsystem = sdl_context.video().map_err(string_to_err)?;
+    let window = video_subsystem
+        .window("Snake Game", 32 * 10, 32 * 10)
+        .position_centered()
+        .build()?;
+    let mut canvas = window.into_canvas().present_vsync().build()?;
+    canvas.set_scale(10.0, 10.0).map_err(string_to_err)?;
+    let creator = canvas.texture_creator();
+    let texture = creator.create_texture_target(PixelFormatEnum::RGB24, 32, 32)?;
+    let event_pump = sdl_context.event_pump().map_err(string_to_err)?;
+    let mut frontend = Frontend::new(clk_micros, texture, canvas, event_pump, debugger, state);
+    frontend.boot(machine)
+}
+
+/// The SDL window showing the framebuffer and feeding the keyboard
+struct Frontend<'a> {
+    running: bool,
+    clk: Duration,
+    display_buffer: [u8; 32 * 3 * 32],
+    event_pump: EventPump,
+    texture: Texture<'a>,
+    canvas: WindowCanvas,
+    debugger: Debugger,
+    state: PathBuf,
+}
+
+impl<'a> Frontend<'a> {
+    fn new(
+        clk_micros: u64,
+        texture: Texture<'a>,
+        canvas: WindowCanvas,
+        event_pump: EventPump,
+        debugger: Debugger,
+        state: &Path,
+    ) -> Self {
+        Frontend {
+            running: false,
+            clk: Duration::from_micros(clk_micros),
+            display_buffer: [0; 32 * 3 * 32],
+            event_pump,
+            texture,
+            canvas,
+            debugger,
+            state: state.to_path_buf(),
+        }
+    }
+
+    fn boot(&mut self, machine: &mut Machine) -> anyhow::Result<()> {
+        self.running = true;
+        let mut boot_start = Instant::now();
+        let mut boot_cycles = machine.cycles();
+        while self.running {
+            let mut stdout = io::stdout();
+            if let Some(index) = self.debugger.check_breakpoints(machine, &mut stdout)? {
+                println!("breakpoint {index}");
+                self.debugger
+                    .repl(machine, io::stdin().lock(), &mut stdout)?;
+                boot_start = Instant::now();
+                boot_cycles = machine.cycles();
+            }
+            let status = machine.step()?;
+            let mut paused = false;
+            for hit in machine.watchpoints_mut().take_hits() {
+                println!("{hit}");
+                paused |= hit.action == WatchAction::Pause;
+            }
+            if matches!(status, Status::Halt) {
+                return Ok(());
+            }
+            if paused {
+                self.debugger
+                    .repl(machine, io::stdin().lock(), &mut stdout)?;
+                boot_start = Instant::now();
+                boot_cycles = machine.cycles();
+            }
+            self.display(machine)?;
+            self.handle_key(machine);
+            // pace against the total elapsed cycles so that short sleeps don't drift
+            let elapsed_cycles = u32::try_from(machine.cycles() - boot_cycles).unwrap_or(u32::MAX);
+            let due = self.clk.saturating_mul(elapsed_cycles);
+            let elapsed = boot_start.elapsed();
+            if elapsed < due {
+                sleep(due - elapsed);
+            }
+        }
+        Ok(())
+    }
+
+    fn display(&mut self, machine: &mut Machine) -> anyhow::Result<()> {
+        let Some(framebuffer) = machine.bus_mut().device_mut::<Framebuffer>() else {
+            return Ok(());
+        };
+        if !framebuffer.take_dirty() {
+            return Ok(());
+        }
+        let mut frame_i = 0;
+        for &pixel in framebuffer.pixels() {
+            let (r, g, b) = color(pixel).rgb();
+            self.display_buffer[frame_i] = r;
+            self.display_buffer[frame_i + 1] = g;
+            self.display_buffer[frame_i + 2] = b;
+            frame_i += 3;
+        }
+        self.texture.update(None, &self.display_buffer, 32 * 3)?;
+        self.canvas
+            .copy(&self.texture, None, None)
+            .map_err(string_to_err)?;
+        self.canvas.present();
+        trace!("[display] buffer displayed");
+        Ok(())
+    }
+
+    fn handle_key(&mut self, machine: &mut Machine) {
+        while let Some(event) = self.event_pump.poll_event() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => self.running = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::UP),
+                    ..
+                } => press_key(machine, 0x77),
+                Event::KeyDown {
+                    keycode: Some(Keycode::DOWN),
+                    ..
+                } => press_key(machine, 0x73),
+                Event::KeyDown {
+                    keycode: Some(Keycode::LEFT),
+                    ..
+                } => press_key(machine, 0x61),
+                Event::KeyDown {
+                    keycode: Some(Keycode::RIGHT),
+                    ..
+                } => press_key(machine, 0x64),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => match savestate::save_file(machine, &self.state) {
+                    Ok(()) => println!("saved the state to {}", self.state.display()),
+                    Err(e) => println!("error: {e:#}"),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => match savestate::load_file(machine, &self.state) {
+                    Ok(()) => println!("restored the state from {}", self.state.display()),
+                    Err(e) => println!("error: {e:#}"),
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+fn press_key(machine: &mut Machine, key: u8) {
+    if let Some(keyboard) = machine.bus_mut().device_mut::<Keyboard>() {
+        keyboard.press(key);
+    }
+}
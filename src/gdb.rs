@@ -0,0 +1,463 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use anyhow::Context;
+use log::{debug, trace, warn};
+
+use crate::{
+    Bus, Flags, Machine, Registers, Status,
+    watch::{Access, WatchAction, Watchpoint},
+};
+
+/// SIGTRAP, reported when stopping on a step, breakpoint or watchpoint
+const SIGTRAP: u8 = 5;
+/// SIGINT, reported when the client interrupts a continue
+const SIGINT: u8 = 2;
+/// SIGILL, reported when the processor halts on a JAM or STP or fails to step
+const SIGILL: u8 = 4;
+/// Steps run between two polls of the socket for an interrupt
+const POLL_STEPS: u32 = 4096;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.b6502.cpu">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Serve one GDB remote serial protocol client until it detaches, kills the
+/// target or disconnects
+pub fn serve<B: Bus>(machine: &mut Machine<B>, stream: TcpStream) -> anyhow::Result<()> {
+    // acks and replies are tiny, don't let them wait for each other
+    stream.set_nodelay(true)?;
+    let mut stub = Stub {
+        reader: BufReader::new(stream.try_clone()?),
+        stream,
+        machine,
+        no_ack: false,
+        swbreak: false,
+        breakpoints: BTreeSet::new(),
+        watches: HashMap::new(),
+    };
+    while let Some(packet) = stub.receive()? {
+        trace!("[gdb] <- {packet}");
+        let Some(reply) = stub.handle(&packet)? else {
+            return Ok(());
+        };
+        stub.send(&reply)?;
+    }
+    debug!("[gdb] client disconnected");
+    Ok(())
+}
+
+struct Stub<'a, B: Bus> {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+    machine: &'a mut Machine<B>,
+    no_ack: bool,
+    /// Whether the client understands the swbreak stop reason
+    swbreak: bool,
+    breakpoints: BTreeSet<u16>,
+    /// The machine watchpoints set by a Z2-Z4 packet, by type, address and length
+    watches: HashMap<(u8, u16, u16), Vec<usize>>,
+}
+
+impl<B: Bus> Stub<'_, B> {
+    /// The next packet, skipping acks and stray interrupts, None at the end of the stream
+    fn receive(&mut self) -> anyhow::Result<Option<String>> {
+        loop {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            if self.reader.read_until(b'#', &mut data)? == 0 || data.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if !self.no_ack {
+                let valid = expected == Some(checksum_of(&data));
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+                if !valid {
+                    continue;
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+        }
+    }
+
+    fn send(&mut self, reply: &str) -> anyhow::Result<()> {
+        trace!("[gdb] -> {reply}");
+        let data = escape(reply.as_bytes());
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(&data);
+        write!(packet, "#{:02x}", checksum_of(&data))?;
+        self.stream.write_all(&packet)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// The reply to a packet, None when the session ends
+    fn handle(&mut self, packet: &str) -> anyhow::Result<Option<String>> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => format!("S{SIGTRAP:02x}"),
+            Some(b'g') => hex(&register_bytes(&self.machine.registers())),
+            Some(b'G') => match parse_hex_bytes(&packet[1..]) {
+                Some(bytes) if bytes.len() == 7 => {
+                    self.machine.set_registers(Registers {
+                        acc: bytes[0],
+                        x: bytes[1],
+                        y: bytes[2],
+                        sp: bytes[3],
+                        pc: u16::from_le_bytes([bytes[4], bytes[5]]),
+                        flags: Flags::from_bits_retain(bytes[6]),
+                    });
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            Some(b'p') => match usize::from_str_radix(&packet[1..], 16) {
+                Ok(n) if n < 6 => {
+                    let bytes = register_bytes(&self.machine.registers());
+                    match n {
+                        4 => hex(&bytes[4..6]),
+                        5 => hex(&bytes[6..7]),
+                        n => hex(&bytes[n..n + 1]),
+                    }
+                }
+                _ => "E01".to_string(),
+            },
+            Some(b'P') => {
+                let parsed = packet[1..].split_once('=').and_then(|(n, value)| {
+                    let n = usize::from_str_radix(n, 16).ok()?;
+                    let bytes = parse_hex_bytes(value)?;
+                    let value = bytes.iter().rev().fold(0u32, |v, &b| v << 8 | b as u32);
+                    (n < 6).then_some((n, value))
+                });
+                match parsed {
+                    Some((n, value)) => {
+                        self.set_register(n, value);
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            Some(b'm') => match parse_addr_len(&packet[1..]) {
+                Some((addr, len)) => {
+                    let bytes: Vec<u8> = (0..len)
+                        .map(|i| self.machine.peek(addr.wrapping_add(i as u16)))
+                        .collect();
+                    hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            Some(b'M') => {
+                let parsed = packet[1..].split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_addr_len(range)?;
+                    let bytes = parse_hex_bytes(data)?;
+                    (bytes.len() == len).then_some((addr, bytes))
+                });
+                match parsed {
+                    Some((addr, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            self.machine.poke(addr.wrapping_add(i as u16), byte);
+                        }
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            Some(b's') => match self.resume_at(&packet[1..]) {
+                Some(()) => self.run(true)?,
+                None => "E01".to_string(),
+            },
+            Some(b'c') => match self.resume_at(&packet[1..]) {
+                Some(()) => self.run(false)?,
+                None => "E01".to_string(),
+            },
+            Some(b'Z') => self.set_point(&packet[1..], true),
+            Some(b'z') => self.set_point(&packet[1..], false),
+            Some(b'H') => "OK".to_string(),
+            Some(b'T') => "OK".to_string(),
+            Some(b'k') => return Ok(None),
+            Some(b'D') => {
+                self.send("OK")?;
+                return Ok(None);
+            }
+            _ => self.query(packet),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            self.swbreak = packet.contains("swbreak+");
+            "PacketSize=4000;QStartNoAckMode+;swbreak+;hwbreak+;qXfer:features:read+".to_string()
+        } else if packet == "QStartNoAckMode" {
+            // the OK itself is still acknowledged
+            self.no_ack = true;
+            "OK".to_string()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_addr_len(range) {
+                Some((offset, len)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = (offset as usize).min(xml.len());
+                    let end = (start + len).min(xml.len());
+                    let more = if end < xml.len() { 'm' } else { 'l' };
+                    format!("{more}{}", String::from_utf8_lossy(&xml[start..end]))
+                }
+                None => "E01".to_string(),
+            }
+        } else {
+            match packet {
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                // anything else is unsupported
+                _ => String::new(),
+            }
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u32) {
+        let mut registers = self.machine.registers();
+        match n {
+            0 => registers.acc = value as u8,
+            1 => registers.x = value as u8,
+            2 => registers.y = value as u8,
+            3 => registers.sp = value as u8,
+            4 => registers.pc = value as u16,
+            _ => registers.flags = Flags::from_bits_retain(value as u8),
+        }
+        self.machine.set_registers(registers);
+    }
+
+    /// `s` and `c` may carry the address to resume at, None when it doesn't parse
+    fn resume_at(&mut self, addr: &str) -> Option<()> {
+        if !addr.is_empty() {
+            let addr = u16::from_str_radix(addr, 16).ok()?;
+            self.set_register(4, addr as u32);
+        }
+        Some(())
+    }
+
+    /// Step once or continue, returns the stop reply. A step that fails, such as an
+    /// unknown opcode when undocumented ones are off, stops with SIGILL too
+    fn run(&mut self, single: bool) -> anyhow::Result<String> {
+        let mut steps = 0u32;
+        loop {
+            let status = match self.machine.step() {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("[gdb] {e:#}");
+                    return Ok(format!("S{SIGILL:02x}"));
+                }
+            };
+            let hits = self.machine.watchpoints_mut().take_hits();
+            // watchpoints set from the REPL only to log don't stop the client
+            let stop = hits.iter().find(|hit| {
+                hit.action == WatchAction::Pause
+                    || self
+                        .watches
+                        .values()
+                        .any(|indices| indices.contains(&hit.index))
+            });
+            if let Some(hit) = stop {
+                let kind = self
+                    .watches
+                    .iter()
+                    .find(|(_, indices)| indices.contains(&hit.index))
+                    .map_or("watch", |((kind, _, _), _)| match kind {
+                        3 => "rwatch",
+                        4 => "awatch",
+                        _ => "watch",
+                    });
+                return Ok(format!("T{SIGTRAP:02x}{kind}:{:04x};", hit.addr));
+            }
+            let pc = self.machine.registers().pc;
+            if matches!(status, Status::Halt) {
+                return Ok(format!("S{SIGILL:02x}"));
+            }
+            if single {
+                return Ok(format!("S{SIGTRAP:02x}"));
+            }
+            if self.breakpoints.contains(&pc) {
+                let reason = if self.swbreak { "swbreak:;" } else { "" };
+                return Ok(format!("T{SIGTRAP:02x}{reason}"));
+            }
+            steps += 1;
+            if steps == POLL_STEPS {
+                steps = 0;
+                if self.interrupted()? {
+                    return Ok(format!("S{SIGINT:02x}"));
+                }
+            }
+        }
+    }
+
+    /// Whether the client sent a ^C, without blocking
+    fn interrupted(&mut self) -> anyhow::Result<bool> {
+        if self.reader.buffer().is_empty() {
+            self.stream.set_nonblocking(true)?;
+            let filled = self.reader.fill_buf().map(|_| ());
+            self.stream.set_nonblocking(false)?;
+            match filled {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                other => other?,
+            }
+        }
+        let buffer = self.reader.buffer();
+        match buffer.iter().position(|&b| b == 0x03) {
+            Some(i) => {
+                self.reader.consume(i + 1);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Z/z packets: type 0 and 1 are breakpoints, 2 to 4 write, read and access watchpoints
+    fn set_point(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        let parsed = (|| {
+            let kind = fields.next()?.parse::<u8>().ok()?;
+            let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+            let len = u16::from_str_radix(fields.next()?, 16).ok()?;
+            Some((kind, addr, len))
+        })();
+        let Some((kind, addr, len)) = parsed else {
+            return "E01".to_string();
+        };
+        match (kind, insert) {
+            (0 | 1, true) => {
+                self.breakpoints.insert(addr);
+            }
+            (0 | 1, false) => {
+                self.breakpoints.remove(&addr);
+            }
+            (2..=4, true) => {
+                // a repeated insert would orphan the watchpoints of the first
+                if self.watches.contains_key(&(kind, addr, len)) {
+                    return "OK".to_string();
+                }
+                let end = addr.saturating_add(len.max(1) - 1);
+                let accesses: &[Access] = match kind {
+                    2 => &[Access::Write],
+                    3 => &[Access::Read],
+                    _ => &[Access::Read, Access::Write],
+                };
+                let indices = accesses
+                    .iter()
+                    .map(|&access| {
+                        self.machine.watchpoints_mut().add(Watchpoint {
+                            range: addr..=end,
+                            access,
+                            action: WatchAction::Pause,
+                        })
+                    })
+                    .collect();
+                self.watches.insert((kind, addr, len), indices);
+            }
+            (2..=4, false) => {
+                for index in self.watches.remove(&(kind, addr, len)).unwrap_or_default() {
+                    self.machine.watchpoints_mut().remove(index);
+                }
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+}
+
+/// Registers in the order of the target description, pc little endian
+fn register_bytes(r: &Registers) -> [u8; 7] {
+    let [lo, hi] = r.pc.to_le_bytes();
+    [
+        r.acc,
+        r.x,
+        r.y,
+        r.sp,
+        lo,
+        hi,
+        (r.flags | Flags::UNUSED).bits(),
+    ]
+}
+
+pub(crate) fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+pub(crate) fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'#' | b'$' | b'}' | b'*') {
+            escaped.extend_from_slice(&[b'}', b ^ 0x20]);
+        } else {
+            escaped.push(b);
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = data.iter();
+    let mut unescaped = Vec::with_capacity(data.len());
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'}' => unescaped.push(bytes.next().map_or(b, |&b| b ^ 0x20)),
+            b => unescaped.push(b),
+        }
+    }
+    unescaped
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `addr,length` in hex
+fn parse_addr_len(s: &str) -> Option<(u16, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = u32::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    (addr <= 0xFFFF && len <= 0x10000).then_some((addr as u16, len))
+}
+
+/// Wait for one client on `port` of the loopback interface
+pub fn listen(port: u16) -> anyhow::Result<TcpStream> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", port))
+        .with_context(|| format!("listening on port {port}"))?;
+    println!("waiting for gdb on 127.0.0.1:{port}");
+    let (stream, peer) = listener.accept()?;
+    println!("gdb connected from {peer}");
+    Ok(stream)
+}
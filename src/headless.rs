@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::{Bus, Machine, Mnemonic, ModeKind, Status, opcodes};
+
+const BRK: u8 = 0x00;
+
+/// When a headless run gives up, every limit is optional
+#[derive(Debug, Default, Clone)]
+pub struct Limits {
+    pub cycles: Option<u64>,
+    pub instructions: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Stop before executing a BRK instead of jumping through the IRQ vector
+    pub stop_on_brk: bool,
+    /// Stop on a jump or a taken branch to itself, how test programs signal their result
+    pub stop_on_trap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halt,
+    Brk,
+    Trap,
+    CycleLimit,
+    InstructionLimit,
+    Timeout,
+    /// The execution differs from the reference log
+    Diverged,
+    /// Every line of the reference log matched
+    LogEnd,
+}
+
+impl StopReason {
+    /// Process exit code: 0 when the program stopped by itself, 2 on a limit,
+    /// 3 on a timeout and 4 when it diverged from the reference log
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            StopReason::Halt | StopReason::Brk | StopReason::Trap | StopReason::LogEnd => 0,
+            StopReason::CycleLimit | StopReason::InstructionLimit => 2,
+            StopReason::Timeout => 3,
+            StopReason::Diverged => 4,
+        }
+    }
+}
+
+/// Run the machine without any frontend until it stops or hits one of the limits
+pub fn run<B: Bus>(machine: &mut Machine<B>, limits: &Limits) -> anyhow::Result<StopReason> {
+    run_with(machine, limits, |_| Ok(None))
+}
+
+/// Like [`run`], calling `before_step` ahead of every instruction, which may stop the run
+pub fn run_with<B: Bus>(
+    machine: &mut Machine<B>,
+    limits: &Limits,
+    mut before_step: impl FnMut(&Machine<B>) -> anyhow::Result<Option<StopReason>>,
+) -> anyhow::Result<StopReason> {
+    let start = Instant::now();
+    let start_cycles = machine.cycles();
+    let mut instructions = 0u64;
+    loop {
+        if limits.stop_on_brk && machine.peek(machine.registers().pc) == BRK {
+            return Ok(StopReason::Brk);
+        }
+        if limits
+            .cycles
+            .is_some_and(|max| machine.cycles() - start_cycles >= max)
+        {
+            return Ok(StopReason::CycleLimit);
+        }
+        if limits.instructions.is_some_and(|max| instructions >= max) {
+            return Ok(StopReason::InstructionLimit);
+        }
+        // checking the clock every instruction is measurably slow
+        if instructions.is_multiple_of(1024)
+            && limits.timeout.is_some_and(|max| start.elapsed() >= max)
+        {
+            return Ok(StopReason::Timeout);
+        }
+        if let Some(reason) = before_step(machine)? {
+            return Ok(reason);
+        }
+        let pc = machine.registers().pc;
+        if let Status::Halt = machine.step()? {
+            debug!("[headless] halted after {} instructions", instructions);
+            return Ok(StopReason::Halt);
+        }
+        instructions += 1;
+        if limits.stop_on_trap && machine.registers().pc == pc && is_trap(machine, pc) {
+            return Ok(StopReason::Trap);
+        }
+    }
+}
+
+/// Whether the instruction at `pc` is a JMP or a branch to itself
+pub fn is_trap<B: Bus>(machine: &Machine<B>, pc: u16) -> bool {
+    let opcode = &opcodes(machine.cpu())[machine.peek(pc) as usize];
+    let operand = |i: u16| machine.peek(pc.wrapping_add(i));
+    match (opcode.mnemonic, opcode.mode) {
+        (Mnemonic::Jmp, ModeKind::Absolute) => u16::from_le_bytes([operand(1), operand(2)]) == pc,
+        (_, ModeKind::Relative) => operand(1) as i8 == -2,
+        _ => false,
+    }
+}
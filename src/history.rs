@@ -0,0 +1,112 @@
+use std::{collections::VecDeque, ops::RangeInclusive};
+
+use crate::{Flags, callstack::CallChange};
+
+/// The processor state before a step, everything but memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+    pub(crate) acc: u8,
+    pub(crate) x: u8,
+    pub(crate) y: u8,
+    pub(crate) flags: Flags,
+    pub(crate) sp: usize,
+    pub(crate) pc: usize,
+    pub(crate) bpc: usize,
+    pub(crate) cycles: u64,
+    pub(crate) waiting: bool,
+    pub(crate) nmi_line: bool,
+    pub(crate) nmi_pending: bool,
+}
+
+/// What undoing one step restores: the processor state and the memory it overwrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Undo {
+    pub(crate) snapshot: Snapshot,
+    writes: Vec<(u16, u8)>,
+    pub(crate) call: Option<CallChange>,
+}
+
+impl Undo {
+    /// Address of the instruction the step executed
+    pub fn pc(&self) -> u16 {
+        self.snapshot.pc as u16
+    }
+
+    /// The addresses written by the step with the values they held before, in order
+    pub fn writes(&self) -> &[(u16, u8)] {
+        &self.writes
+    }
+
+    pub fn wrote(&self, range: &RangeInclusive<u16>) -> bool {
+        self.writes.iter().any(|(addr, _)| range.contains(addr))
+    }
+}
+
+/// A bounded undo log, the oldest steps are dropped first
+#[derive(Debug)]
+pub struct History {
+    steps: VecDeque<Undo>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            steps: VecDeque::with_capacity(capacity.min(1 << 16)),
+            capacity,
+        }
+    }
+
+    /// Number of steps that can be undone
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn begin(&mut self, snapshot: Snapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            // reuse the oldest entry's allocation
+            let mut oldest = self.steps.pop_front().unwrap_or_else(|| Undo {
+                snapshot,
+                writes: Vec::new(),
+                call: None,
+            });
+            oldest.snapshot = snapshot;
+            oldest.writes.clear();
+            oldest.call = None;
+            self.steps.push_back(oldest);
+        } else {
+            self.steps.push_back(Undo {
+                snapshot,
+                writes: Vec::new(),
+                call: None,
+            });
+        }
+    }
+
+    pub(crate) fn record_write(&mut self, addr: u16, old: u8) {
+        if let Some(step) = self.steps.back_mut() {
+            step.writes.push((addr, old));
+        }
+    }
+
+    pub(crate) fn record_call(&mut self, change: CallChange) {
+        if let Some(step) = self.steps.back_mut() {
+            step.call = Some(change);
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Undo> {
+        self.steps.pop_back()
+    }
+}
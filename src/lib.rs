@@ -0,0 +1,24 @@
+//! 6502 processor emulator core, independent from any frontend
+pub mod breakpoint;
+pub mod bus;
+pub mod callstack;
+pub mod cpu;
+pub mod dap;
+pub mod debugger;
+pub mod expr;
+pub mod gdb;
+pub mod headless;
+pub mod history;
+pub mod loader;
+pub mod opcode;
+pub mod operation;
+pub mod profile;
+pub mod savestate;
+pub mod symbols;
+pub mod trace;
+pub mod watch;
+
+pub use bus::{Bus, Device, MemoryMap};
+pub use cpu::{Cpu, Flags, Machine, MachineBuilder, Registers, Status};
+pub use opcode::{Mnemonic, ModeKind, Opcode, opcodes};
+pub use operation::{AddressingMode, Index, Operation, parse_opcode};
@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use anyhow::{Context, bail};
+
+use crate::{Bus, Machine, bus::ADDRESS_SPACE};
+
+/// File formats, told apart by their extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A flat binary, loaded at an address given by the user
+    Raw,
+    /// A Commodore program file starting with its load address
+    Prg,
+    /// Intel HEX, `.hex` or `.ihx`
+    IntelHex,
+    /// Motorola S-record, `.s19`, `.s28`, `.s37`, `.srec` or `.mot`
+    SRecord,
+    /// MOS Technology paper tape as punched by the KIM-1, `.pap` or `.ptp`
+    PaperTape,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Format {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("prg") => Format::Prg,
+            Some("hex" | "ihx") => Format::IntelHex,
+            Some("s19" | "s28" | "s37" | "srec" | "mot") => Format::SRecord,
+            Some("pap" | "ptp") => Format::PaperTape,
+            _ => Format::Raw,
+        }
+    }
+}
+
+/// A run of bytes placed at a fixed address
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// A program made of segments, with the address execution starts at when the format has one
+#[derive(Debug, Clone, Default)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    pub entry: Option<u16>,
+}
+
+impl Image {
+    /// A flat binary placed at `addr`, which is also its entry point
+    pub fn raw(data: Vec<u8>, addr: u16) -> Image {
+        Image {
+            segments: vec![Segment { addr, data }],
+            entry: Some(addr),
+        }
+    }
+
+    /// A Commodore program file, the first two bytes are the little endian load address
+    pub fn prg(data: &[u8]) -> anyhow::Result<Image> {
+        let [lsb, msb, body @ ..] = data else {
+            bail!("prg file shorter than its load address");
+        };
+        Ok(Image::raw(body.to_vec(), u16::from_le_bytes([*lsb, *msb])))
+    }
+
+    /// Read a file in the format given by its extension. Raw binaries are loaded at `addr`,
+    /// which also overrides the load address of a `.prg` file. The text formats carry
+    /// their own addresses
+    pub fn open(path: &Path, addr: Option<u16>) -> anyhow::Result<Image> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let text = || String::from_utf8_lossy(&data).into_owned();
+        let format = Format::from_path(path);
+        let image = match (format, addr) {
+            (Format::Raw, Some(addr)) => Ok(Image::raw(data, addr)),
+            (Format::Raw, None) => bail!("{} needs a load address", path.display()),
+            (Format::Prg, None) => Image::prg(&data),
+            (Format::Prg, Some(addr)) => {
+                let mut image = Image::prg(&data)?;
+                image.segments[0].addr = addr;
+                image.entry = Some(addr);
+                Ok(image)
+            }
+            (_, Some(_)) => bail!("{} carries its own addresses", path.display()),
+            (Format::IntelHex, None) => Image::intel_hex(&text()),
+            (Format::SRecord, None) => Image::srecord(&text()),
+            (Format::PaperTape, None) => Image::paper_tape(&text()),
+        };
+        image.with_context(|| format!("loading {}", path.display()))
+    }
+
+    /// Intel HEX: `:LLAAAATT<data>CC`, the checksum makes the sum of all the bytes zero.
+    /// Extended segment and linear address records must keep the data within 64K,
+    /// start address records set the entry point
+    pub fn intel_hex(text: &str) -> anyhow::Result<Image> {
+        let mut image = Image::default();
+        let mut base = 0u32;
+        for (n, line) in records(text) {
+            let Some(record) = line.strip_prefix(':') else {
+                bail!("line {n}: expected a record starting with ':'");
+            };
+            let bytes = hex_bytes(record, n)?;
+            let [count, hi, lo, kind, _, ..] = bytes[..] else {
+                bail!("line {n}: record too short");
+            };
+            if bytes.len() != count as usize + 5 {
+                bail!(
+                    "line {n}: length {} does not match the count {count}",
+                    bytes.len().saturating_sub(5)
+                );
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+                bail!("line {n}: checksum mismatch");
+            }
+            let data = &bytes[4..bytes.len() - 1];
+            let field = |len: usize| -> anyhow::Result<u32> {
+                if data.len() != len {
+                    bail!("line {n}: record type {kind:0>2x} needs {len} bytes");
+                }
+                Ok(data.iter().fold(0, |acc, b| acc << 8 | *b as u32))
+            };
+            match kind {
+                0x00 => {
+                    let addr = base + u16::from_be_bytes([hi, lo]) as u32;
+                    image.push(addr, data, n)?;
+                }
+                0x01 => break,
+                0x02 => base = field(2)? << 4,
+                0x04 => base = field(2)? << 16,
+                // CS:IP and EIP, only the low 16 bits are meaningful here
+                0x03 => image.entry = Some(field(4)? as u16),
+                0x05 => image.entry = Some(field(4)? as u16),
+                _ => bail!("line {n}: unknown record type {kind:0>2x}"),
+            }
+        }
+        Ok(image)
+    }
+
+    /// Motorola S-record: `S<type><count><address><data><checksum>`, the checksum is the
+    /// ones' complement of the sum of the other bytes. S1/S2/S3 carry data, S7/S8/S9 the
+    /// start address
+    pub fn srecord(text: &str) -> anyhow::Result<Image> {
+        let mut image = Image::default();
+        for (n, line) in records(text) {
+            let Some(record) = line.strip_prefix(['S', 's']) else {
+                bail!("line {n}: expected a record starting with 'S'");
+            };
+            let mut chars = record.chars();
+            let Some(kind) = chars.next() else {
+                bail!("line {n}: record too short");
+            };
+            let bytes = hex_bytes(chars.as_str(), n)?;
+            let Some((&count, rest)) = bytes.split_first() else {
+                bail!("line {n}: record too short");
+            };
+            if rest.len() != count as usize {
+                bail!(
+                    "line {n}: length {} does not match the count {count}",
+                    rest.len()
+                );
+            }
+            if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+                bail!("line {n}: checksum mismatch");
+            }
+            let addr_len = match kind {
+                '0' | '1' | '5' | '9' => 2,
+                '2' | '6' | '8' => 3,
+                '3' | '7' => 4,
+                _ => bail!("line {n}: unknown record type S{kind}"),
+            };
+            if rest.len() < addr_len + 1 {
+                bail!("line {n}: record too short");
+            }
+            let addr = rest[..addr_len]
+                .iter()
+                .fold(0u32, |acc, b| acc << 8 | *b as u32);
+            let data = &rest[addr_len..rest.len() - 1];
+            match kind {
+                '1' | '2' | '3' => image.push(addr, data, n)?,
+                // assemblers end with S9 0000 when there is no start address
+                '7' | '8' | '9' if addr != 0 => image.entry = Some(addr as u16),
+                _ => {}
+            }
+        }
+        Ok(image)
+    }
+
+    /// MOS paper tape: `;LLAAAA<data>CCCC`, the checksum is the 16-bit sum of the other
+    /// bytes. The last record has no data and counts the records before it
+    pub fn paper_tape(text: &str) -> anyhow::Result<Image> {
+        let mut image = Image::default();
+        for (records_read, (n, line)) in records(text).enumerate() {
+            let Some(record) = line.strip_prefix(';') else {
+                bail!("line {n}: expected a record starting with ';'");
+            };
+            let bytes = hex_bytes(record, n)?;
+            let [count, hi, lo, ..] = bytes[..] else {
+                bail!("line {n}: record too short");
+            };
+            if bytes.len() != count as usize + 5 {
+                bail!(
+                    "line {n}: length {} does not match the count {count}",
+                    bytes.len().saturating_sub(5)
+                );
+            }
+            let (body, checksum) = bytes.split_at(bytes.len() - 2);
+            let sum = body.iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+            if sum != u16::from_be_bytes([checksum[0], checksum[1]]) {
+                bail!("line {n}: checksum mismatch");
+            }
+            let addr = u16::from_be_bytes([hi, lo]);
+            if count == 0 {
+                if addr as usize != records_read {
+                    bail!("line {n}: {records_read} records read, the last record counts {addr}");
+                }
+                return Ok(image);
+            }
+            image.push(addr as u32, &body[3..], n)?;
+        }
+        bail!("missing the last record")
+    }
+
+    fn push(&mut self, addr: u32, data: &[u8], line: usize) -> anyhow::Result<()> {
+        if addr as usize + data.len() > ADDRESS_SPACE {
+            bail!("line {line}: data at {addr:x} is outside the 64K address space");
+        }
+        self.segments.push(Segment {
+            addr: addr as u16,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Add the segments of another image, keeping the entry point of this one when it has one
+    pub fn merge(&mut self, other: Image) {
+        self.segments.extend(other.segments);
+        self.entry = self.entry.or(other.entry);
+    }
+
+    /// Write every segment into memory and jump to `entry`, or to the image entry point,
+    /// or else to the first segment
+    pub fn load_into<B: Bus>(
+        &self,
+        machine: &mut Machine<B>,
+        entry: Option<u16>,
+    ) -> anyhow::Result<()> {
+        for segment in &self.segments {
+            machine
+                .load(segment.addr as usize, &segment.data)
+                .with_context(|| format!("segment at {:0>4x}", segment.addr))?;
+        }
+        let first = self.segments.first().map(|s| s.addr);
+        if let Some(pc) = entry.or(self.entry).or(first) {
+            machine.jump(pc);
+        }
+        Ok(())
+    }
+}
+
+/// Non empty lines with their 1-based numbers
+fn records(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+fn hex_bytes(digits: &str, line: usize) -> anyhow::Result<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        bail!("line {line}: odd number of hex digits");
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .with_context(|| format!("line {line}: invalid hex digits"))
+        })
+        .collect()
+}
@@ -0,0 +1,865 @@
+use std::fmt::Display;
+
+use crate::cpu::{Cpu, Flags};
+
+use Mnemonic::*;
+use ModeKind::*;
+
+/// Instruction names, shared by every addressing mode of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Adc,
+    Alr,
+    Anc,
+    And,
+    Arr,
+    Asl,
+    Bbr,
+    Bbs,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Bra,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dcp,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Halt,
+    Inc,
+    Inx,
+    Iny,
+    Isc,
+    Jmp,
+    Jsr,
+    Las,
+    Lax,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Lxa,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Phx,
+    Phy,
+    Pla,
+    Plp,
+    Plx,
+    Ply,
+    Rla,
+    Rmb,
+    Rol,
+    Ror,
+    Rra,
+    Rti,
+    Rts,
+    Sax,
+    Sbc,
+    Sbx,
+    Sec,
+    Sed,
+    Sei,
+    Sha,
+    Shx,
+    Shy,
+    Skip,
+    Slo,
+    Smb,
+    Sre,
+    Sta,
+    Stp,
+    Stx,
+    Sty,
+    Stz,
+    Tas,
+    Tax,
+    Tay,
+    Trb,
+    Tsb,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+    Wai,
+    Xaa,
+}
+
+impl Mnemonic {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Adc => "ADC",
+            Alr => "ALR",
+            Anc => "ANC",
+            And => "AND",
+            Arr => "ARR",
+            Asl => "ASL",
+            Bbr => "BBR",
+            Bbs => "BBS",
+            Bcc => "BCC",
+            Bcs => "BCS",
+            Beq => "BEQ",
+            Bit => "BIT",
+            Bmi => "BMI",
+            Bne => "BNE",
+            Bpl => "BPL",
+            Bra => "BRA",
+            Brk => "BRK",
+            Bvc => "BVC",
+            Bvs => "BVS",
+            Clc => "CLC",
+            Cld => "CLD",
+            Cli => "CLI",
+            Clv => "CLV",
+            Cmp => "CMP",
+            Cpx => "CPX",
+            Cpy => "CPY",
+            Dcp => "DCP",
+            Dec => "DEC",
+            Dex => "DEX",
+            Dey => "DEY",
+            Eor => "EOR",
+            Halt => "HALT",
+            Inc => "INC",
+            Inx => "INX",
+            Iny => "INY",
+            Isc => "ISC",
+            Jmp => "JMP",
+            Jsr => "JSR",
+            Las => "LAS",
+            Lax => "LAX",
+            Lda => "LDA",
+            Ldx => "LDX",
+            Ldy => "LDY",
+            Lsr => "LSR",
+            Lxa => "LXA",
+            Nop => "NOP",
+            Ora => "ORA",
+            Pha => "PHA",
+            Php => "PHP",
+            Phx => "PHX",
+            Phy => "PHY",
+            Pla => "PLA",
+            Plp => "PLP",
+            Plx => "PLX",
+            Ply => "PLY",
+            Rla => "RLA",
+            Rmb => "RMB",
+            Rol => "ROL",
+            Ror => "ROR",
+            Rra => "RRA",
+            Rti => "RTI",
+            Rts => "RTS",
+            Sax => "SAX",
+            Sbc => "SBC",
+            Sbx => "SBX",
+            Sec => "SEC",
+            Sed => "SED",
+            Sei => "SEI",
+            Sha => "SHA",
+            Shx => "SHX",
+            Shy => "SHY",
+            Skip => "NOP",
+            Slo => "SLO",
+            Smb => "SMB",
+            Sre => "SRE",
+            Sta => "STA",
+            Stp => "STP",
+            Stx => "STX",
+            Sty => "STY",
+            Stz => "STZ",
+            Tas => "TAS",
+            Tax => "TAX",
+            Tay => "TAY",
+            Trb => "TRB",
+            Tsb => "TSB",
+            Tsx => "TSX",
+            Txa => "TXA",
+            Txs => "TXS",
+            Tya => "TYA",
+            Wai => "WAI",
+            Xaa => "XAA",
+        }
+    }
+
+    /// Status flags the instruction may change
+    pub const fn flags(&self) -> Flags {
+        const NZ: Flags = Flags::NEGATIVE.union(Flags::ZERO);
+        const NZC: Flags = NZ.union(Flags::CARRY);
+        const NVZC: Flags = NZC.union(Flags::OVERFLOW);
+        match self {
+            Adc | Sbc | Rra | Isc | Arr => NVZC,
+            Asl | Lsr | Rol | Ror | Slo | Rla | Sre | Cmp | Cpx | Cpy | Dcp | Anc | Alr | Sbx => {
+                NZC
+            }
+            And | Eor | Ora | Lda | Ldx | Ldy | Lax | Las | Lxa | Xaa | Pla | Plx | Ply | Tax
+            | Tay | Txa | Tya | Tsx | Inx | Iny | Dex | Dey | Inc | Dec => NZ,
+            Bit => Flags::NEGATIVE.union(Flags::OVERFLOW).union(Flags::ZERO),
+            Trb | Tsb => Flags::ZERO,
+            Clc | Sec => Flags::CARRY,
+            Cli | Sei | Brk => Flags::INTERRUPT_DISABLE,
+            Cld | Sed => Flags::DECIMAL,
+            Clv => Flags::OVERFLOW,
+            // B and the unused bit only exist on the stack
+            Plp | Rti => Flags::all().difference(Flags::BREAK.union(Flags::UNUSED)),
+            _ => Flags::empty(),
+        }
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Addressing mode of an opcode, without the operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeKind {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+    ZeroPageIndirect,
+    AbsoluteIndexedIndirect,
+    ZeroPageRelative,
+}
+
+impl ModeKind {
+    /// Number of operand bytes following the opcode
+    pub const fn operand_len(&self) -> u8 {
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | Relative | IndexedIndirect
+            | IndirectIndexed | ZeroPageIndirect => 1,
+            Absolute
+            | AbsoluteX
+            | AbsoluteY
+            | Indirect
+            | AbsoluteIndexedIndirect
+            | ZeroPageRelative => 2,
+        }
+    }
+}
+
+impl Display for ModeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let syntax = match self {
+            Implied => "",
+            Accumulator => "A",
+            Immediate => "#$nn",
+            ZeroPage | Relative => "$nn",
+            ZeroPageX => "$nn,X",
+            ZeroPageY => "$nn,Y",
+            Absolute => "$nnnn",
+            AbsoluteX => "$nnnn,X",
+            AbsoluteY => "$nnnn,Y",
+            Indirect => "($nnnn)",
+            IndexedIndirect => "($nn,X)",
+            IndirectIndexed => "($nn),Y",
+            ZeroPageIndirect => "($nn)",
+            AbsoluteIndexedIndirect => "($nnnn,X)",
+            ZeroPageRelative => "$nn,$nn",
+        };
+        f.pad(syntax)
+    }
+}
+
+/// Everything known about one opcode before executing it
+#[derive(Debug, Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: Mnemonic,
+    pub mode: ModeKind,
+    /// Instruction length in bytes, opcode included
+    pub length: u8,
+    /// Base clock cycles, without page crossing, branch and decimal penalties
+    pub cycles: u8,
+    /// Not part of the documented instruction set, rejected by `--strict`
+    pub undocumented: bool,
+}
+
+impl Opcode {
+    /// Status flags the instruction may change
+    pub const fn flags(&self) -> Flags {
+        self.mnemonic.flags()
+    }
+
+    /// Bit number of the Rockwell bit instructions, encoded in the opcode
+    pub const fn bit(opcode: u8) -> u8 {
+        (opcode >> 4) & 0x07
+    }
+}
+
+const fn op(mnemonic: Mnemonic, mode: ModeKind, cycles: u8) -> Opcode {
+    // BRK skips a padding byte after the opcode
+    let padding = matches!(mnemonic, Brk) as u8;
+    Opcode {
+        mnemonic,
+        mode,
+        length: 1 + mode.operand_len() + padding,
+        cycles,
+        undocumented: false,
+    }
+}
+
+const fn u(mnemonic: Mnemonic, mode: ModeKind, cycles: u8) -> Opcode {
+    Opcode {
+        undocumented: true,
+        ..op(mnemonic, mode, cycles)
+    }
+}
+
+/// Returns the opcode table of a processor variant
+pub fn opcodes(cpu: Cpu) -> &'static [Opcode; 256] {
+    match cpu {
+        Cpu::Nmos => &NMOS_OPCODES,
+        Cpu::Cmos => &CMOS_OPCODES,
+    }
+}
+
+/// The NMOS 6502, every undefined opcode is one of the undocumented instructions
+#[rustfmt::skip]
+pub const NMOS_OPCODES: [Opcode; 256] = [
+    /* 00 */ op(Brk, Implied, 7),
+    /* 01 */ op(Ora, IndexedIndirect, 6),
+    /* 02 */ u(Halt, Implied, 2),
+    /* 03 */ u(Slo, IndexedIndirect, 8),
+    /* 04 */ u(Skip, ZeroPage, 3),
+    /* 05 */ op(Ora, ZeroPage, 3),
+    /* 06 */ op(Asl, ZeroPage, 5),
+    /* 07 */ u(Slo, ZeroPage, 5),
+    /* 08 */ op(Php, Implied, 3),
+    /* 09 */ op(Ora, Immediate, 2),
+    /* 0A */ op(Asl, Accumulator, 2),
+    /* 0B */ u(Anc, Immediate, 2),
+    /* 0C */ u(Skip, Absolute, 4),
+    /* 0D */ op(Ora, Absolute, 4),
+    /* 0E */ op(Asl, Absolute, 6),
+    /* 0F */ u(Slo, Absolute, 6),
+    /* 10 */ op(Bpl, Relative, 2),
+    /* 11 */ op(Ora, IndirectIndexed, 5),
+    /* 12 */ u(Halt, Implied, 2),
+    /* 13 */ u(Slo, IndirectIndexed, 8),
+    /* 14 */ u(Skip, ZeroPageX, 4),
+    /* 15 */ op(Ora, ZeroPageX, 4),
+    /* 16 */ op(Asl, ZeroPageX, 6),
+    /* 17 */ u(Slo, ZeroPageX, 6),
+    /* 18 */ op(Clc, Implied, 2),
+    /* 19 */ op(Ora, AbsoluteY, 4),
+    /* 1A */ u(Nop, Implied, 2),
+    /* 1B */ u(Slo, AbsoluteY, 7),
+    /* 1C */ u(Skip, AbsoluteX, 4),
+    /* 1D */ op(Ora, AbsoluteX, 4),
+    /* 1E */ op(Asl, AbsoluteX, 7),
+    /* 1F */ u(Slo, AbsoluteX, 7),
+    /* 20 */ op(Jsr, Absolute, 6),
+    /* 21 */ op(And, IndexedIndirect, 6),
+    /* 22 */ u(Halt, Implied, 2),
+    /* 23 */ u(Rla, IndexedIndirect, 8),
+    /* 24 */ op(Bit, ZeroPage, 3),
+    /* 25 */ op(And, ZeroPage, 3),
+    /* 26 */ op(Rol, ZeroPage, 5),
+    /* 27 */ u(Rla, ZeroPage, 5),
+    /* 28 */ op(Plp, Implied, 4),
+    /* 29 */ op(And, Immediate, 2),
+    /* 2A */ op(Rol, Accumulator, 2),
+    /* 2B */ u(Anc, Immediate, 2),
+    /* 2C */ op(Bit, Absolute, 4),
+    /* 2D */ op(And, Absolute, 4),
+    /* 2E */ op(Rol, Absolute, 6),
+    /* 2F */ u(Rla, Absolute, 6),
+    /* 30 */ op(Bmi, Relative, 2),
+    /* 31 */ op(And, IndirectIndexed, 5),
+    /* 32 */ u(Halt, Implied, 2),
+    /* 33 */ u(Rla, IndirectIndexed, 8),
+    /* 34 */ u(Skip, ZeroPageX, 4),
+    /* 35 */ op(And, ZeroPageX, 4),
+    /* 36 */ op(Rol, ZeroPageX, 6),
+    /* 37 */ u(Rla, ZeroPageX, 6),
+    /* 38 */ op(Sec, Implied, 2),
+    /* 39 */ op(And, AbsoluteY, 4),
+    /* 3A */ u(Nop, Implied, 2),
+    /* 3B */ u(Rla, AbsoluteY, 7),
+    /* 3C */ u(Skip, AbsoluteX, 4),
+    /* 3D */ op(And, AbsoluteX, 4),
+    /* 3E */ op(Rol, AbsoluteX, 7),
+    /* 3F */ u(Rla, AbsoluteX, 7),
+    /* 40 */ op(Rti, Implied, 6),
+    /* 41 */ op(Eor, IndexedIndirect, 6),
+    /* 42 */ u(Halt, Implied, 2),
+    /* 43 */ u(Sre, IndexedIndirect, 8),
+    /* 44 */ u(Skip, ZeroPage, 3),
+    /* 45 */ op(Eor, ZeroPage, 3),
+    /* 46 */ op(Lsr, ZeroPage, 5),
+    /* 47 */ u(Sre, ZeroPage, 5),
+    /* 48 */ op(Pha, Implied, 3),
+    /* 49 */ op(Eor, Immediate, 2),
+    /* 4A */ op(Lsr, Accumulator, 2),
+    /* 4B */ u(Alr, Immediate, 2),
+    /* 4C */ op(Jmp, Absolute, 3),
+    /* 4D */ op(Eor, Absolute, 4),
+    /* 4E */ op(Lsr, Absolute, 6),
+    /* 4F */ u(Sre, Absolute, 6),
+    /* 50 */ op(Bvc, Relative, 2),
+    /* 51 */ op(Eor, IndirectIndexed, 5),
+    /* 52 */ u(Halt, Implied, 2),
+    /* 53 */ u(Sre, IndirectIndexed, 8),
+    /* 54 */ u(Skip, ZeroPageX, 4),
+    /* 55 */ op(Eor, ZeroPageX, 4),
+    /* 56 */ op(Lsr, ZeroPageX, 6),
+    /* 57 */ u(Sre, ZeroPageX, 6),
+    /* 58 */ op(Cli, Implied, 2),
+    /* 59 */ op(Eor, AbsoluteY, 4),
+    /* 5A */ u(Nop, Implied, 2),
+    /* 5B */ u(Sre, AbsoluteY, 7),
+    /* 5C */ u(Skip, AbsoluteX, 4),
+    /* 5D */ op(Eor, AbsoluteX, 4),
+    /* 5E */ op(Lsr, AbsoluteX, 7),
+    /* 5F */ u(Sre, AbsoluteX, 7),
+    /* 60 */ op(Rts, Implied, 6),
+    /* 61 */ op(Adc, IndexedIndirect, 6),
+    /* 62 */ u(Halt, Implied, 2),
+    /* 63 */ u(Rra, IndexedIndirect, 8),
+    /* 64 */ u(Skip, ZeroPage, 3),
+    /* 65 */ op(Adc, ZeroPage, 3),
+    /* 66 */ op(Ror, ZeroPage, 5),
+    /* 67 */ u(Rra, ZeroPage, 5),
+    /* 68 */ op(Pla, Implied, 4),
+    /* 69 */ op(Adc, Immediate, 2),
+    /* 6A */ op(Ror, Accumulator, 2),
+    /* 6B */ u(Arr, Immediate, 2),
+    /* 6C */ op(Jmp, Indirect, 5),
+    /* 6D */ op(Adc, Absolute, 4),
+    /* 6E */ op(Ror, Absolute, 6),
+    /* 6F */ u(Rra, Absolute, 6),
+    /* 70 */ op(Bvs, Relative, 2),
+    /* 71 */ op(Adc, IndirectIndexed, 5),
+    /* 72 */ u(Halt, Implied, 2),
+    /* 73 */ u(Rra, IndirectIndexed, 8),
+    /* 74 */ u(Skip, ZeroPageX, 4),
+    /* 75 */ op(Adc, ZeroPageX, 4),
+    /* 76 */ op(Ror, ZeroPageX, 6),
+    /* 77 */ u(Rra, ZeroPageX, 6),
+    /* 78 */ op(Sei, Implied, 2),
+    /* 79 */ op(Adc, AbsoluteY, 4),
+    /* 7A */ u(Nop, Implied, 2),
+    /* 7B */ u(Rra, AbsoluteY, 7),
+    /* 7C */ u(Skip, AbsoluteX, 4),
+    /* 7D */ op(Adc, AbsoluteX, 4),
+    /* 7E */ op(Ror, AbsoluteX, 7),
+    /* 7F */ u(Rra, AbsoluteX, 7),
+    /* 80 */ u(Skip, Immediate, 2),
+    /* 81 */ op(Sta, IndexedIndirect, 6),
+    /* 82 */ u(Skip, Immediate, 2),
+    /* 83 */ u(Sax, IndexedIndirect, 6),
+    /* 84 */ op(Sty, ZeroPage, 3),
+    /* 85 */ op(Sta, ZeroPage, 3),
+    /* 86 */ op(Stx, ZeroPage, 3),
+    /* 87 */ u(Sax, ZeroPage, 3),
+    /* 88 */ op(Dey, Implied, 2),
+    /* 89 */ u(Skip, Immediate, 2),
+    /* 8A */ op(Txa, Implied, 2),
+    /* 8B */ u(Xaa, Immediate, 2),
+    /* 8C */ op(Sty, Absolute, 4),
+    /* 8D */ op(Sta, Absolute, 4),
+    /* 8E */ op(Stx, Absolute, 4),
+    /* 8F */ u(Sax, Absolute, 4),
+    /* 90 */ op(Bcc, Relative, 2),
+    /* 91 */ op(Sta, IndirectIndexed, 6),
+    /* 92 */ u(Halt, Implied, 2),
+    /* 93 */ u(Sha, IndirectIndexed, 6),
+    /* 94 */ op(Sty, ZeroPageX, 4),
+    /* 95 */ op(Sta, ZeroPageX, 4),
+    /* 96 */ op(Stx, ZeroPageX, 4),
+    /* 97 */ u(Sax, ZeroPageY, 4),
+    /* 98 */ op(Tya, Implied, 2),
+    /* 99 */ op(Sta, AbsoluteY, 5),
+    /* 9A */ op(Txs, Implied, 2),
+    /* 9B */ u(Tas, AbsoluteY, 5),
+    /* 9C */ u(Shy, AbsoluteX, 5),
+    /* 9D */ op(Sta, AbsoluteX, 5),
+    /* 9E */ u(Shx, AbsoluteY, 5),
+    /* 9F */ u(Sha, AbsoluteY, 5),
+    /* A0 */ op(Ldy, Immediate, 2),
+    /* A1 */ op(Lda, IndexedIndirect, 6),
+    /* A2 */ op(Ldx, Immediate, 2),
+    /* A3 */ u(Lax, IndexedIndirect, 6),
+    /* A4 */ op(Ldy, ZeroPage, 3),
+    /* A5 */ op(Lda, ZeroPage, 3),
+    /* A6 */ op(Ldx, ZeroPage, 3),
+    /* A7 */ u(Lax, ZeroPage, 3),
+    /* A8 */ op(Tay, Implied, 2),
+    /* A9 */ op(Lda, Immediate, 2),
+    /* AA */ op(Tax, Implied, 2),
+    /* AB */ u(Lxa, Immediate, 2),
+    /* AC */ op(Ldy, Absolute, 4),
+    /* AD */ op(Lda, Absolute, 4),
+    /* AE */ op(Ldx, Absolute, 4),
+    /* AF */ u(Lax, Absolute, 4),
+    /* B0 */ op(Bcs, Relative, 2),
+    /* B1 */ op(Lda, IndirectIndexed, 5),
+    /* B2 */ u(Halt, Implied, 2),
+    /* B3 */ u(Lax, IndirectIndexed, 5),
+    /* B4 */ op(Ldy, ZeroPageX, 4),
+    /* B5 */ op(Lda, ZeroPageX, 4),
+    /* B6 */ op(Ldx, ZeroPageY, 4),
+    /* B7 */ u(Lax, ZeroPageY, 4),
+    /* B8 */ op(Clv, Implied, 2),
+    /* B9 */ op(Lda, AbsoluteY, 4),
+    /* BA */ op(Tsx, Implied, 2),
+    /* BB */ u(Las, AbsoluteY, 4),
+    /* BC */ op(Ldy, AbsoluteX, 4),
+    /* BD */ op(Lda, AbsoluteX, 4),
+    /* BE */ op(Ldx, AbsoluteY, 4),
+    /* BF */ u(Lax, AbsoluteY, 4),
+    /* C0 */ op(Cpy, Immediate, 2),
+    /* C1 */ op(Cmp, IndexedIndirect, 6),
+    /* C2 */ u(Skip, Immediate, 2),
+    /* C3 */ u(Dcp, IndexedIndirect, 8),
+    /* C4 */ op(Cpy, ZeroPage, 3),
+    /* C5 */ op(Cmp, ZeroPage, 3),
+    /* C6 */ op(Dec, ZeroPage, 5),
+    /* C7 */ u(Dcp, ZeroPage, 5),
+    /* C8 */ op(Iny, Implied, 2),
+    /* C9 */ op(Cmp, Immediate, 2),
+    /* CA */ op(Dex, Implied, 2),
+    /* CB */ u(Sbx, Immediate, 2),
+    /* CC */ op(Cpy, Absolute, 4),
+    /* CD */ op(Cmp, Absolute, 4),
+    /* CE */ op(Dec, Absolute, 6),
+    /* CF */ u(Dcp, Absolute, 6),
+    /* D0 */ op(Bne, Relative, 2),
+    /* D1 */ op(Cmp, IndirectIndexed, 5),
+    /* D2 */ u(Halt, Implied, 2),
+    /* D3 */ u(Dcp, IndirectIndexed, 8),
+    /* D4 */ u(Skip, ZeroPageX, 4),
+    /* D5 */ op(Cmp, ZeroPageX, 4),
+    /* D6 */ op(Dec, ZeroPageX, 6),
+    /* D7 */ u(Dcp, ZeroPageX, 6),
+    /* D8 */ op(Cld, Implied, 2),
+    /* D9 */ op(Cmp, AbsoluteY, 4),
+    /* DA */ u(Nop, Implied, 2),
+    /* DB */ u(Dcp, AbsoluteY, 7),
+    /* DC */ u(Skip, AbsoluteX, 4),
+    /* DD */ op(Cmp, AbsoluteX, 4),
+    /* DE */ op(Dec, AbsoluteX, 7),
+    /* DF */ u(Dcp, AbsoluteX, 7),
+    /* E0 */ op(Cpx, Immediate, 2),
+    /* E1 */ op(Sbc, IndexedIndirect, 6),
+    /* E2 */ u(Skip, Immediate, 2),
+    /* E3 */ u(Isc, IndexedIndirect, 8),
+    /* E4 */ op(Cpx, ZeroPage, 3),
+    /* E5 */ op(Sbc, ZeroPage, 3),
+    /* E6 */ op(Inc, ZeroPage, 5),
+    /* E7 */ u(Isc, ZeroPage, 5),
+    /* E8 */ op(Inx, Implied, 2),
+    /* E9 */ op(Sbc, Immediate, 2),
+    /* EA */ op(Nop, Implied, 2),
+    /* EB */ u(Sbc, Immediate, 2),
+    /* EC */ op(Cpx, Absolute, 4),
+    /* ED */ op(Sbc, Absolute, 4),
+    /* EE */ op(Inc, Absolute, 6),
+    /* EF */ u(Isc, Absolute, 6),
+    /* F0 */ op(Beq, Relative, 2),
+    /* F1 */ op(Sbc, IndirectIndexed, 5),
+    /* F2 */ u(Halt, Implied, 2),
+    /* F3 */ u(Isc, IndirectIndexed, 8),
+    /* F4 */ u(Skip, ZeroPageX, 4),
+    /* F5 */ op(Sbc, ZeroPageX, 4),
+    /* F6 */ op(Inc, ZeroPageX, 6),
+    /* F7 */ u(Isc, ZeroPageX, 6),
+    /* F8 */ op(Sed, Implied, 2),
+    /* F9 */ op(Sbc, AbsoluteY, 4),
+    /* FA */ u(Nop, Implied, 2),
+    /* FB */ u(Isc, AbsoluteY, 7),
+    /* FC */ u(Skip, AbsoluteX, 4),
+    /* FD */ op(Sbc, AbsoluteX, 4),
+    /* FE */ op(Inc, AbsoluteX, 7),
+    /* FF */ u(Isc, AbsoluteX, 7),
+];
+
+/// The WDC/Rockwell 65C02, every undefined opcode is a NOP
+#[rustfmt::skip]
+pub const CMOS_OPCODES: [Opcode; 256] = [
+    /* 00 */ op(Brk, Implied, 7),
+    /* 01 */ op(Ora, IndexedIndirect, 6),
+    /* 02 */ op(Skip, Immediate, 2),
+    /* 03 */ op(Nop, Implied, 1),
+    /* 04 */ op(Tsb, ZeroPage, 5),
+    /* 05 */ op(Ora, ZeroPage, 3),
+    /* 06 */ op(Asl, ZeroPage, 5),
+    /* 07 */ op(Rmb, ZeroPage, 5),
+    /* 08 */ op(Php, Implied, 3),
+    /* 09 */ op(Ora, Immediate, 2),
+    /* 0A */ op(Asl, Accumulator, 2),
+    /* 0B */ op(Nop, Implied, 1),
+    /* 0C */ op(Tsb, Absolute, 6),
+    /* 0D */ op(Ora, Absolute, 4),
+    /* 0E */ op(Asl, Absolute, 6),
+    /* 0F */ op(Bbr, ZeroPageRelative, 5),
+    /* 10 */ op(Bpl, Relative, 2),
+    /* 11 */ op(Ora, IndirectIndexed, 5),
+    /* 12 */ op(Ora, ZeroPageIndirect, 5),
+    /* 13 */ op(Nop, Implied, 1),
+    /* 14 */ op(Trb, ZeroPage, 5),
+    /* 15 */ op(Ora, ZeroPageX, 4),
+    /* 16 */ op(Asl, ZeroPageX, 6),
+    /* 17 */ op(Rmb, ZeroPage, 5),
+    /* 18 */ op(Clc, Implied, 2),
+    /* 19 */ op(Ora, AbsoluteY, 4),
+    /* 1A */ op(Inc, Accumulator, 2),
+    /* 1B */ op(Nop, Implied, 1),
+    /* 1C */ op(Trb, Absolute, 6),
+    /* 1D */ op(Ora, AbsoluteX, 4),
+    /* 1E */ op(Asl, AbsoluteX, 6),
+    /* 1F */ op(Bbr, ZeroPageRelative, 5),
+    /* 20 */ op(Jsr, Absolute, 6),
+    /* 21 */ op(And, IndexedIndirect, 6),
+    /* 22 */ op(Skip, Immediate, 2),
+    /* 23 */ op(Nop, Implied, 1),
+    /* 24 */ op(Bit, ZeroPage, 3),
+    /* 25 */ op(And, ZeroPage, 3),
+    /* 26 */ op(Rol, ZeroPage, 5),
+    /* 27 */ op(Rmb, ZeroPage, 5),
+    /* 28 */ op(Plp, Implied, 4),
+    /* 29 */ op(And, Immediate, 2),
+    /* 2A */ op(Rol, Accumulator, 2),
+    /* 2B */ op(Nop, Implied, 1),
+    /* 2C */ op(Bit, Absolute, 4),
+    /* 2D */ op(And, Absolute, 4),
+    /* 2E */ op(Rol, Absolute, 6),
+    /* 2F */ op(Bbr, ZeroPageRelative, 5),
+    /* 30 */ op(Bmi, Relative, 2),
+    /* 31 */ op(And, IndirectIndexed, 5),
+    /* 32 */ op(And, ZeroPageIndirect, 5),
+    /* 33 */ op(Nop, Implied, 1),
+    /* 34 */ op(Bit, ZeroPageX, 4),
+    /* 35 */ op(And, ZeroPageX, 4),
+    /* 36 */ op(Rol, ZeroPageX, 6),
+    /* 37 */ op(Rmb, ZeroPage, 5),
+    /* 38 */ op(Sec, Implied, 2),
+    /* 39 */ op(And, AbsoluteY, 4),
+    /* 3A */ op(Dec, Accumulator, 2),
+    /* 3B */ op(Nop, Implied, 1),
+    /* 3C */ op(Bit, AbsoluteX, 4),
+    /* 3D */ op(And, AbsoluteX, 4),
+    /* 3E */ op(Rol, AbsoluteX, 6),
+    /* 3F */ op(Bbr, ZeroPageRelative, 5),
+    /* 40 */ op(Rti, Implied, 6),
+    /* 41 */ op(Eor, IndexedIndirect, 6),
+    /* 42 */ op(Skip, Immediate, 2),
+    /* 43 */ op(Nop, Implied, 1),
+    /* 44 */ op(Skip, ZeroPage, 3),
+    /* 45 */ op(Eor, ZeroPage, 3),
+    /* 46 */ op(Lsr, ZeroPage, 5),
+    /* 47 */ op(Rmb, ZeroPage, 5),
+    /* 48 */ op(Pha, Implied, 3),
+    /* 49 */ op(Eor, Immediate, 2),
+    /* 4A */ op(Lsr, Accumulator, 2),
+    /* 4B */ op(Nop, Implied, 1),
+    /* 4C */ op(Jmp, Absolute, 3),
+    /* 4D */ op(Eor, Absolute, 4),
+    /* 4E */ op(Lsr, Absolute, 6),
+    /* 4F */ op(Bbr, ZeroPageRelative, 5),
+    /* 50 */ op(Bvc, Relative, 2),
+    /* 51 */ op(Eor, IndirectIndexed, 5),
+    /* 52 */ op(Eor, ZeroPageIndirect, 5),
+    /* 53 */ op(Nop, Implied, 1),
+    /* 54 */ op(Skip, ZeroPageX, 4),
+    /* 55 */ op(Eor, ZeroPageX, 4),
+    /* 56 */ op(Lsr, ZeroPageX, 6),
+    /* 57 */ op(Rmb, ZeroPage, 5),
+    /* 58 */ op(Cli, Implied, 2),
+    /* 59 */ op(Eor, AbsoluteY, 4),
+    /* 5A */ op(Phy, Implied, 3),
+    /* 5B */ op(Nop, Implied, 1),
+    /* 5C */ op(Skip, Absolute, 8),
+    /* 5D */ op(Eor, AbsoluteX, 4),
+    /* 5E */ op(Lsr, AbsoluteX, 6),
+    /* 5F */ op(Bbr, ZeroPageRelative, 5),
+    /* 60 */ op(Rts, Implied, 6),
+    /* 61 */ op(Adc, IndexedIndirect, 6),
+    /* 62 */ op(Skip, Immediate, 2),
+    /* 63 */ op(Nop, Implied, 1),
+    /* 64 */ op(Stz, ZeroPage, 3),
+    /* 65 */ op(Adc, ZeroPage, 3),
+    /* 66 */ op(Ror, ZeroPage, 5),
+    /* 67 */ op(Rmb, ZeroPage, 5),
+    /* 68 */ op(Pla, Implied, 4),
+    /* 69 */ op(Adc, Immediate, 2),
+    /* 6A */ op(Ror, Accumulator, 2),
+    /* 6B */ op(Nop, Implied, 1),
+    /* 6C */ op(Jmp, Indirect, 6),
+    /* 6D */ op(Adc, Absolute, 4),
+    /* 6E */ op(Ror, Absolute, 6),
+    /* 6F */ op(Bbr, ZeroPageRelative, 5),
+    /* 70 */ op(Bvs, Relative, 2),
+    /* 71 */ op(Adc, IndirectIndexed, 5),
+    /* 72 */ op(Adc, ZeroPageIndirect, 5),
+    /* 73 */ op(Nop, Implied, 1),
+    /* 74 */ op(Stz, ZeroPageX, 4),
+    /* 75 */ op(Adc, ZeroPageX, 4),
+    /* 76 */ op(Ror, ZeroPageX, 6),
+    /* 77 */ op(Rmb, ZeroPage, 5),
+    /* 78 */ op(Sei, Implied, 2),
+    /* 79 */ op(Adc, AbsoluteY, 4),
+    /* 7A */ op(Ply, Implied, 4),
+    /* 7B */ op(Nop, Implied, 1),
+    /* 7C */ op(Jmp, AbsoluteIndexedIndirect, 6),
+    /* 7D */ op(Adc, AbsoluteX, 4),
+    /* 7E */ op(Ror, AbsoluteX, 6),
+    /* 7F */ op(Bbr, ZeroPageRelative, 5),
+    /* 80 */ op(Bra, Relative, 2),
+    /* 81 */ op(Sta, IndexedIndirect, 6),
+    /* 82 */ op(Skip, Immediate, 2),
+    /* 83 */ op(Nop, Implied, 1),
+    /* 84 */ op(Sty, ZeroPage, 3),
+    /* 85 */ op(Sta, ZeroPage, 3),
+    /* 86 */ op(Stx, ZeroPage, 3),
+    /* 87 */ op(Smb, ZeroPage, 5),
+    /* 88 */ op(Dey, Implied, 2),
+    /* 89 */ op(Bit, Immediate, 2),
+    /* 8A */ op(Txa, Implied, 2),
+    /* 8B */ op(Nop, Implied, 1),
+    /* 8C */ op(Sty, Absolute, 4),
+    /* 8D */ op(Sta, Absolute, 4),
+    /* 8E */ op(Stx, Absolute, 4),
+    /* 8F */ op(Bbs, ZeroPageRelative, 5),
+    /* 90 */ op(Bcc, Relative, 2),
+    /* 91 */ op(Sta, IndirectIndexed, 6),
+    /* 92 */ op(Sta, ZeroPageIndirect, 5),
+    /* 93 */ op(Nop, Implied, 1),
+    /* 94 */ op(Sty, ZeroPageX, 4),
+    /* 95 */ op(Sta, ZeroPageX, 4),
+    /* 96 */ op(Stx, ZeroPageX, 4),
+    /* 97 */ op(Smb, ZeroPage, 5),
+    /* 98 */ op(Tya, Implied, 2),
+    /* 99 */ op(Sta, AbsoluteY, 5),
+    /* 9A */ op(Txs, Implied, 2),
+    /* 9B */ op(Nop, Implied, 1),
+    /* 9C */ op(Stz, Absolute, 4),
+    /* 9D */ op(Sta, AbsoluteX, 5),
+    /* 9E */ op(Stz, AbsoluteX, 5),
+    /* 9F */ op(Bbs, ZeroPageRelative, 5),
+    /* A0 */ op(Ldy, Immediate, 2),
+    /* A1 */ op(Lda, IndexedIndirect, 6),
+    /* A2 */ op(Ldx, Immediate, 2),
+    /* A3 */ op(Nop, Implied, 1),
+    /* A4 */ op(Ldy, ZeroPage, 3),
+    /* A5 */ op(Lda, ZeroPage, 3),
+    /* A6 */ op(Ldx, ZeroPage, 3),
+    /* A7 */ op(Smb, ZeroPage, 5),
+    /* A8 */ op(Tay, Implied, 2),
+    /* A9 */ op(Lda, Immediate, 2),
+    /* AA */ op(Tax, Implied, 2),
+    /* AB */ op(Nop, Implied, 1),
+    /* AC */ op(Ldy, Absolute, 4),
+    /* AD */ op(Lda, Absolute, 4),
+    /* AE */ op(Ldx, Absolute, 4),
+    /* AF */ op(Bbs, ZeroPageRelative, 5),
+    /* B0 */ op(Bcs, Relative, 2),
+    /* B1 */ op(Lda, IndirectIndexed, 5),
+    /* B2 */ op(Lda, ZeroPageIndirect, 5),
+    /* B3 */ op(Nop, Implied, 1),
+    /* B4 */ op(Ldy, ZeroPageX, 4),
+    /* B5 */ op(Lda, ZeroPageX, 4),
+    /* B6 */ op(Ldx, ZeroPageY, 4),
+    /* B7 */ op(Smb, ZeroPage, 5),
+    /* B8 */ op(Clv, Implied, 2),
+    /* B9 */ op(Lda, AbsoluteY, 4),
+    /* BA */ op(Tsx, Implied, 2),
+    /* BB */ op(Nop, Implied, 1),
+    /* BC */ op(Ldy, AbsoluteX, 4),
+    /* BD */ op(Lda, AbsoluteX, 4),
+    /* BE */ op(Ldx, AbsoluteY, 4),
+    /* BF */ op(Bbs, ZeroPageRelative, 5),
+    /* C0 */ op(Cpy, Immediate, 2),
+    /* C1 */ op(Cmp, IndexedIndirect, 6),
+    /* C2 */ op(Skip, Immediate, 2),
+    /* C3 */ op(Nop, Implied, 1),
+    /* C4 */ op(Cpy, ZeroPage, 3),
+    /* C5 */ op(Cmp, ZeroPage, 3),
+    /* C6 */ op(Dec, ZeroPage, 5),
+    /* C7 */ op(Smb, ZeroPage, 5),
+    /* C8 */ op(Iny, Implied, 2),
+    /* C9 */ op(Cmp, Immediate, 2),
+    /* CA */ op(Dex, Implied, 2),
+    /* CB */ op(Wai, Implied, 3),
+    /* CC */ op(Cpy, Absolute, 4),
+    /* CD */ op(Cmp, Absolute, 4),
+    /* CE */ op(Dec, Absolute, 6),
+    /* CF */ op(Bbs, ZeroPageRelative, 5),
+    /* D0 */ op(Bne, Relative, 2),
+    /* D1 */ op(Cmp, IndirectIndexed, 5),
+    /* D2 */ op(Cmp, ZeroPageIndirect, 5),
+    /* D3 */ op(Nop, Implied, 1),
+    /* D4 */ op(Skip, ZeroPageX, 4),
+    /* D5 */ op(Cmp, ZeroPageX, 4),
+    /* D6 */ op(Dec, ZeroPageX, 6),
+    /* D7 */ op(Smb, ZeroPage, 5),
+    /* D8 */ op(Cld, Implied, 2),
+    /* D9 */ op(Cmp, AbsoluteY, 4),
+    /* DA */ op(Phx, Implied, 3),
+    /* DB */ op(Stp, Implied, 3),
+    /* DC */ op(Skip, Absolute, 4),
+    /* DD */ op(Cmp, AbsoluteX, 4),
+    /* DE */ op(Dec, AbsoluteX, 7),
+    /* DF */ op(Bbs, ZeroPageRelative, 5),
+    /* E0 */ op(Cpx, Immediate, 2),
+    /* E1 */ op(Sbc, IndexedIndirect, 6),
+    /* E2 */ op(Skip, Immediate, 2),
+    /* E3 */ op(Nop, Implied, 1),
+    /* E4 */ op(Cpx, ZeroPage, 3),
+    /* E5 */ op(Sbc, ZeroPage, 3),
+    /* E6 */ op(Inc, ZeroPage, 5),
+    /* E7 */ op(Smb, ZeroPage, 5),
+    /* E8 */ op(Inx, Implied, 2),
+    /* E9 */ op(Sbc, Immediate, 2),
+    /* EA */ op(Nop, Implied, 2),
+    /* EB */ op(Nop, Implied, 1),
+    /* EC */ op(Cpx, Absolute, 4),
+    /* ED */ op(Sbc, Absolute, 4),
+    /* EE */ op(Inc, Absolute, 6),
+    /* EF */ op(Bbs, ZeroPageRelative, 5),
+    /* F0 */ op(Beq, Relative, 2),
+    /* F1 */ op(Sbc, IndirectIndexed, 5),
+    /* F2 */ op(Sbc, ZeroPageIndirect, 5),
+    /* F3 */ op(Nop, Implied, 1),
+    /* F4 */ op(Skip, ZeroPageX, 4),
+    /* F5 */ op(Sbc, ZeroPageX, 4),
+    /* F6 */ op(Inc, ZeroPageX, 6),
+    /* F7 */ op(Smb, ZeroPage, 5),
+    /* F8 */ op(Sed, Implied, 2),
+    /* F9 */ op(Sbc, AbsoluteY, 4),
+    /* FA */ op(Plx, Implied, 4),
+    /* FB */ op(Nop, Implied, 1),
+    /* FC */ op(Skip, Absolute, 4),
+    /* FD */ op(Sbc, AbsoluteX, 4),
+    /* FE */ op(Inc, AbsoluteX, 7),
+    /* FF */ op(Bbs, ZeroPageRelative, 5),
+];
@@ -0,0 +1,575 @@
+use std::fmt::Display;
+
+use crate::{
+    cpu::Cpu,
+    opcode::{Mnemonic, ModeKind, Opcode, opcodes},
+};
+
+impl<T> Cursor for T where T: Iterator<Item = u8> {}
+
+#[derive(Debug)]
+pub enum Index {
+    None,
+    X,
+    Y,
+}
+
+#[derive(Debug)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate(u8),
+    ZeroPage(u8, Index),
+    Relative(i8),
+    Absolute(u16, Index),
+    Indirect(u16),
+    /// Indexed Indirect | LDA ($40, X) -> *(val(X) + $40)
+    IndexedIndirect(u8),
+    /// Indirect Indexed | LDA ($40), Y -> *($0040) + val(Y)
+    IndirectIndexed(u8),
+    /// Zero Page Indirect (65C02) | LDA ($40) -> *($0040)
+    ZeroPageIndirect(u8),
+    /// Absolute Indexed Indirect (65C02) | JMP ($4000, X) -> *($4000 + val(X))
+    AbsoluteIndexedIndirect(u16),
+    /// Zero Page and Relative (65C02) | BBR0 $40, $10
+    ZeroPageRelative(u8, i8),
+}
+
+impl Display for AddressingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use AddressingMode::*;
+        match self {
+            Implied => write!(f, ""),
+            Accumulator => write!(f, "A"),
+            Immediate(n) => write!(f, "#${:0>2x}", *n),
+            ZeroPage(n, idx) => match idx {
+                Index::None => write!(f, "${:0>2x}", *n),
+                Index::X => write!(f, "${:0>2x},X", *n),
+                Index::Y => write!(f, "${:0>2x},Y", *n),
+            },
+            Relative(n) => write!(f, "${:0>2x}", *n),
+            Absolute(n, idx) => match idx {
+                Index::None => write!(f, "${:0>4x}", *n),
+                Index::X => write!(f, "${:0>4x},X", *n),
+                Index::Y => write!(f, "${:0>4x},Y", *n),
+            },
+            Indirect(n) => write!(f, "(${:0>4x})", *n),
+            IndexedIndirect(n) => write!(f, "(${:0>2x},X)", *n),
+            IndirectIndexed(n) => write!(f, "(${:0>2x}),Y", *n),
+            ZeroPageIndirect(n) => write!(f, "(${:0>2x})", *n),
+            AbsoluteIndexedIndirect(n) => write!(f, "(${:0>4x},X)", *n),
+            ZeroPageRelative(n, r) => write!(f, "${:0>2x},${:0>2x}", *n, *r),
+        }
+    }
+}
+
+pub type Mode = AddressingMode;
+
+pub trait Cursor: Iterator<Item = u8> {
+    fn next_2(&mut self) -> Option<(u8, u8)> {
+        match (self.next(), self.next()) {
+            (Some(lsb), Some(msb)) => Some((lsb, msb)),
+            _ => None,
+        }
+    }
+
+    fn need_u8(&mut self) -> anyhow::Result<u8> {
+        if let Some(b) = self.next() {
+            Ok(b)
+        } else {
+            anyhow::bail!("insufficient byte");
+        }
+    }
+
+    fn need_i8(&mut self) -> anyhow::Result<i8> {
+        if let Some(b) = self.next() {
+            Ok(b as i8)
+        } else {
+            anyhow::bail!("insufficient byte");
+        }
+    }
+
+    fn need_u16(&mut self) -> anyhow::Result<u16> {
+        if let Some((lsb, msb)) = self.next_2() {
+            Ok(u16::from_le_bytes([lsb, msb]))
+        } else {
+            anyhow::bail!("insufficient bytes for u16")
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Operation {
+    /// Add with carry
+    Adc(Mode),
+    /// Bitwise And
+    And(Mode),
+    /// Arithmetic shift left
+    Asl(Mode),
+    /// Branch if carry clear
+    Bcc(Mode),
+    /// Branch if carry set
+    Bcs(Mode),
+    /// Branch if equal
+    Beq(Mode),
+    /// Bit Test
+    Bit(Mode),
+    /// Branch if Minus
+    Bmi(Mode),
+    /// Branch if not euqal
+    Bne(Mode),
+    /// Branch if plus
+    Bpl(Mode),
+    /// Break (software IRQ)
+    Brk,
+    /// Branch if overflow clear
+    Bvc(Mode),
+    /// Branch if overflow set
+    Bvs(Mode),
+    /// Clear carray
+    Clc,
+    /// Clear decimal
+    Cld,
+    /// Clear interrupt disable
+    Cli,
+    /// Clear overflow
+    Clv,
+    /// Compare A
+    Cmp(Mode),
+    /// Compare X
+    Cpx(Mode),
+    /// Compare Y
+    Cpy(Mode),
+    /// Decrement Memory (M=M-1)
+    Dec(Mode),
+    /// Decrement X (X=X-1)
+    Dex,
+    /// Decrement Y (Y=Y-1)
+    Dey,
+    /// Bitwise Exclusive or
+    Eor(Mode),
+    /// Increment memory
+    Inc(Mode),
+    /// Increment X
+    Inx,
+    /// Increment Y
+    Iny,
+    /// Jump
+    Jmp(Mode),
+    /// Jump to subroutine
+    Jsr(Mode),
+    /// Load A
+    Lda(Mode),
+    /// Load X
+    Ldx(Mode),
+    /// Load Y
+    Ldy(Mode),
+    /// Logical shift right
+    Lsr(Mode),
+    /// No oepration
+    Nop,
+    /// Bitwise or
+    Ora(Mode),
+    /// Push A
+    Pha,
+    /// Push processor status
+    Php,
+    /// pull A
+    Pla,
+    /// Pull processor status
+    Plp,
+    /// Rotate left
+    Rol(Mode),
+    /// Rotate right
+    Ror(Mode),
+    /// Return from interrupt
+    Rti,
+    /// Return from Subroutine
+    Rts,
+    /// Substract with carry
+    Sbc(Mode),
+    /// Set Carry
+    Sec,
+    /// Set Decimal
+    Sed,
+    /// Set interrupt disable
+    Sei,
+    /// Store A
+    Sta(Mode),
+    /// Store X
+    Stx(Mode),
+    /// Store Y
+    Sty(Mode),
+    /// Transfer A to X
+    Tax,
+    /// Transfer A to Y
+    Tay,
+    /// Transfer Stack Pointer to X
+    Tsx,
+    /// Transfer X to A
+    Txa,
+    /// Transfer X to Stack Pointer
+    Txs,
+    /// Transfer Y to A
+    Tya,
+
+    /// No operation, reading and discarding the operand (undocumented)
+    Skip(Mode),
+    /// Load A and X (undocumented)
+    Lax(Mode),
+    /// Store A & X (undocumented)
+    Sax(Mode),
+    /// Decrement memory then compare with A (undocumented)
+    Dcp(Mode),
+    /// Increment memory then subtract with carry (undocumented)
+    Isc(Mode),
+    /// Shift memory left then bitwise or into A (undocumented)
+    Slo(Mode),
+    /// Rotate memory left then bitwise and into A (undocumented)
+    Rla(Mode),
+    /// Shift memory right then bitwise exclusive or into A (undocumented)
+    Sre(Mode),
+    /// Rotate memory right then add with carry (undocumented)
+    Rra(Mode),
+    /// Bitwise and, copying N into C (undocumented)
+    Anc(Mode),
+    /// Bitwise and then shift A right (undocumented)
+    Alr(Mode),
+    /// Bitwise and then rotate A right (undocumented)
+    Arr(Mode),
+    /// X = A & X minus immediate, without borrow (undocumented)
+    Sbx(Mode),
+    /// Load A, X and stack pointer with memory & stack pointer (undocumented)
+    Las(Mode),
+    /// Load A and X with (A | magic) & immediate (undocumented, unstable)
+    Lxa(Mode),
+    /// A = (A | magic) & X & immediate (undocumented, unstable)
+    Xaa(Mode),
+    /// Store A & X & (high byte + 1) (undocumented, unstable)
+    Sha(Mode),
+    /// Store X & (high byte + 1) (undocumented, unstable)
+    Shx(Mode),
+    /// Store Y & (high byte + 1) (undocumented, unstable)
+    Shy(Mode),
+    /// Stack pointer = A & X, then store it & (high byte + 1) (undocumented, unstable)
+    Tas(Mode),
+
+    /// Branch always (65C02)
+    Bra(Mode),
+    /// Push X (65C02)
+    Phx,
+    /// Push Y (65C02)
+    Phy,
+    /// Pull X (65C02)
+    Plx,
+    /// Pull Y (65C02)
+    Ply,
+    /// Store zero (65C02)
+    Stz(Mode),
+    /// Test and reset bits (65C02)
+    Trb(Mode),
+    /// Test and set bits (65C02)
+    Tsb(Mode),
+    /// Reset memory bit (Rockwell 65C02)
+    Rmb(u8, Mode),
+    /// Set memory bit (Rockwell 65C02)
+    Smb(u8, Mode),
+    /// Branch on bit reset (Rockwell 65C02)
+    Bbr(u8, Mode),
+    /// Branch on bit set (Rockwell 65C02)
+    Bbs(u8, Mode),
+    /// Wait for interrupt (65C02)
+    Wai,
+    /// Stop the processor (65C02)
+    Stp,
+
+    Halt,
+}
+
+impl Operation {
+    fn new(mnemonic: Mnemonic, mode: Mode, bit: u8) -> Operation {
+        use Mnemonic as M;
+        use Operation::*;
+        match mnemonic {
+            M::Adc => Adc(mode),
+            M::And => And(mode),
+            M::Asl => Asl(mode),
+            M::Bcc => Bcc(mode),
+            M::Bcs => Bcs(mode),
+            M::Beq => Beq(mode),
+            M::Bit => Bit(mode),
+            M::Bmi => Bmi(mode),
+            M::Bne => Bne(mode),
+            M::Bpl => Bpl(mode),
+            M::Brk => Brk,
+            M::Bvc => Bvc(mode),
+            M::Bvs => Bvs(mode),
+            M::Clc => Clc,
+            M::Cld => Cld,
+            M::Cli => Cli,
+            M::Clv => Clv,
+            M::Cmp => Cmp(mode),
+            M::Cpx => Cpx(mode),
+            M::Cpy => Cpy(mode),
+            M::Dec => Dec(mode),
+            M::Dex => Dex,
+            M::Dey => Dey,
+            M::Eor => Eor(mode),
+            M::Inc => Inc(mode),
+            M::Inx => Inx,
+            M::Iny => Iny,
+            M::Jmp => Jmp(mode),
+            M::Jsr => Jsr(mode),
+            M::Lda => Lda(mode),
+            M::Ldx => Ldx(mode),
+            M::Ldy => Ldy(mode),
+            M::Lsr => Lsr(mode),
+            M::Nop => Nop,
+            M::Ora => Ora(mode),
+            M::Pha => Pha,
+            M::Php => Php,
+            M::Pla => Pla,
+            M::Plp => Plp,
+            M::Rol => Rol(mode),
+            M::Ror => Ror(mode),
+            M::Rti => Rti,
+            M::Rts => Rts,
+            M::Sbc => Sbc(mode),
+            M::Sec => Sec,
+            M::Sed => Sed,
+            M::Sei => Sei,
+            M::Sta => Sta(mode),
+            M::Stx => Stx(mode),
+            M::Sty => Sty(mode),
+            M::Tax => Tax,
+            M::Tay => Tay,
+            M::Tsx => Tsx,
+            M::Txa => Txa,
+            M::Txs => Txs,
+            M::Tya => Tya,
+            M::Skip => Skip(mode),
+            M::Lax => Lax(mode),
+            M::Sax => Sax(mode),
+            M::Dcp => Dcp(mode),
+            M::Isc => Isc(mode),
+            M::Slo => Slo(mode),
+            M::Rla => Rla(mode),
+            M::Sre => Sre(mode),
+            M::Rra => Rra(mode),
+            M::Anc => Anc(mode),
+            M::Alr => Alr(mode),
+            M::Arr => Arr(mode),
+            M::Sbx => Sbx(mode),
+            M::Las => Las(mode),
+            M::Lxa => Lxa(mode),
+            M::Xaa => Xaa(mode),
+            M::Sha => Sha(mode),
+            M::Shx => Shx(mode),
+            M::Shy => Shy(mode),
+            M::Tas => Tas(mode),
+            M::Bra => Bra(mode),
+            M::Phx => Phx,
+            M::Phy => Phy,
+            M::Plx => Plx,
+            M::Ply => Ply,
+            M::Stz => Stz(mode),
+            M::Trb => Trb(mode),
+            M::Tsb => Tsb(mode),
+            M::Rmb => Rmb(bit, mode),
+            M::Smb => Smb(bit, mode),
+            M::Bbr => Bbr(bit, mode),
+            M::Bbs => Bbs(bit, mode),
+            M::Wai => Wai,
+            M::Stp => Stp,
+            M::Halt => Halt,
+        }
+    }
+
+    pub fn mnemonic(&self) -> Mnemonic {
+        use Mnemonic as M;
+        use Operation::*;
+        match self {
+            Adc(_) => M::Adc,
+            And(_) => M::And,
+            Asl(_) => M::Asl,
+            Bcc(_) => M::Bcc,
+            Bcs(_) => M::Bcs,
+            Beq(_) => M::Beq,
+            Bit(_) => M::Bit,
+            Bmi(_) => M::Bmi,
+            Bne(_) => M::Bne,
+            Bpl(_) => M::Bpl,
+            Brk => M::Brk,
+            Bvc(_) => M::Bvc,
+            Bvs(_) => M::Bvs,
+            Clc => M::Clc,
+            Cld => M::Cld,
+            Cli => M::Cli,
+            Clv => M::Clv,
+            Cmp(_) => M::Cmp,
+            Cpx(_) => M::Cpx,
+            Cpy(_) => M::Cpy,
+            Dec(_) => M::Dec,
+            Dex => M::Dex,
+            Dey => M::Dey,
+            Eor(_) => M::Eor,
+            Inc(_) => M::Inc,
+            Inx => M::Inx,
+            Iny => M::Iny,
+            Jmp(_) => M::Jmp,
+            Jsr(_) => M::Jsr,
+            Lda(_) => M::Lda,
+            Ldx(_) => M::Ldx,
+            Ldy(_) => M::Ldy,
+            Lsr(_) => M::Lsr,
+            Nop => M::Nop,
+            Ora(_) => M::Ora,
+            Pha => M::Pha,
+            Php => M::Php,
+            Pla => M::Pla,
+            Plp => M::Plp,
+            Rol(_) => M::Rol,
+            Ror(_) => M::Ror,
+            Rti => M::Rti,
+            Rts => M::Rts,
+            Sbc(_) => M::Sbc,
+            Sec => M::Sec,
+            Sed => M::Sed,
+            Sei => M::Sei,
+            Sta(_) => M::Sta,
+            Stx(_) => M::Stx,
+            Sty(_) => M::Sty,
+            Tax => M::Tax,
+            Tay => M::Tay,
+            Tsx => M::Tsx,
+            Txa => M::Txa,
+            Txs => M::Txs,
+            Tya => M::Tya,
+            Skip(_) => M::Skip,
+            Lax(_) => M::Lax,
+            Sax(_) => M::Sax,
+            Dcp(_) => M::Dcp,
+            Isc(_) => M::Isc,
+            Slo(_) => M::Slo,
+            Rla(_) => M::Rla,
+            Sre(_) => M::Sre,
+            Rra(_) => M::Rra,
+            Anc(_) => M::Anc,
+            Alr(_) => M::Alr,
+            Arr(_) => M::Arr,
+            Sbx(_) => M::Sbx,
+            Las(_) => M::Las,
+            Lxa(_) => M::Lxa,
+            Xaa(_) => M::Xaa,
+            Sha(_) => M::Sha,
+            Shx(_) => M::Shx,
+            Shy(_) => M::Shy,
+            Tas(_) => M::Tas,
+            Bra(_) => M::Bra,
+            Phx => M::Phx,
+            Phy => M::Phy,
+            Plx => M::Plx,
+            Ply => M::Ply,
+            Stz(_) => M::Stz,
+            Trb(_) => M::Trb,
+            Tsb(_) => M::Tsb,
+            Rmb(..) => M::Rmb,
+            Smb(..) => M::Smb,
+            Bbr(..) => M::Bbr,
+            Bbs(..) => M::Bbs,
+            Wai => M::Wai,
+            Stp => M::Stp,
+            Halt => M::Halt,
+        }
+    }
+
+    /// The addressing mode and operand, None for implied instructions
+    pub fn mode(&self) -> Option<&Mode> {
+        use Operation::*;
+        match self {
+            Adc(mode) | And(mode) | Asl(mode) | Bcc(mode) | Bcs(mode) | Beq(mode) | Bit(mode)
+            | Bmi(mode) | Bne(mode) | Bpl(mode) | Bvc(mode) | Bvs(mode) | Cmp(mode) | Cpx(mode)
+            | Cpy(mode) | Dec(mode) | Eor(mode) | Inc(mode) | Jmp(mode) | Jsr(mode) | Lda(mode)
+            | Ldx(mode) | Ldy(mode) | Lsr(mode) | Ora(mode) | Rol(mode) | Ror(mode) | Sbc(mode)
+            | Sta(mode) | Stx(mode) | Sty(mode) | Skip(mode) | Lax(mode) | Sax(mode)
+            | Dcp(mode) | Isc(mode) | Slo(mode) | Rla(mode) | Sre(mode) | Rra(mode) | Anc(mode)
+            | Alr(mode) | Arr(mode) | Sbx(mode) | Las(mode) | Lxa(mode) | Xaa(mode) | Sha(mode)
+            | Shx(mode) | Shy(mode) | Tas(mode) | Bra(mode) | Stz(mode) | Trb(mode) | Tsb(mode) => {
+                Some(mode)
+            }
+            Rmb(_, mode) | Smb(_, mode) | Bbr(_, mode) | Bbs(_, mode) => Some(mode),
+            _ => None,
+        }
+    }
+
+    /// Bit number of the Rockwell bit instructions
+    pub fn bit(&self) -> Option<u8> {
+        use Operation::*;
+        match self {
+            Rmb(bit, _) | Smb(bit, _) | Bbr(bit, _) | Bbs(bit, _) => Some(*bit),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        if let Some(bit) = self.bit() {
+            write!(f, "{}", bit)?;
+        }
+        match self.mode() {
+            None | Some(AddressingMode::Implied) => Ok(()),
+            Some(mode) => write!(f, " {}", mode),
+        }
+    }
+}
+
+pub fn parse_opcode<T: Cursor>(
+    cursor: &mut T,
+    cpu: Cpu,
+    undocumented: bool,
+) -> anyhow::Result<Option<Operation>> {
+    let operator = match cursor.next() {
+        Some(operator) => operator,
+        None => return Ok(None),
+    };
+    let opcode = &opcodes(cpu)[operator as usize];
+    if opcode.undocumented && !undocumented {
+        // jams still lock up, and 0xFF is kept as the halt marker of strict programs
+        if opcode.mnemonic == Mnemonic::Halt || operator == 0xFF {
+            return Ok(Some(Operation::Halt));
+        }
+        anyhow::bail!("unknown operator {:x}", operator)
+    }
+    let mode = match opcode.mode {
+        ModeKind::Implied => AddressingMode::Implied,
+        ModeKind::Accumulator => AddressingMode::Accumulator,
+        ModeKind::Immediate => AddressingMode::Immediate(cursor.need_u8()?),
+        ModeKind::ZeroPage => AddressingMode::ZeroPage(cursor.need_u8()?, Index::None),
+        ModeKind::ZeroPageX => AddressingMode::ZeroPage(cursor.need_u8()?, Index::X),
+        ModeKind::ZeroPageY => AddressingMode::ZeroPage(cursor.need_u8()?, Index::Y),
+        ModeKind::Relative => AddressingMode::Relative(cursor.need_i8()?),
+        ModeKind::Absolute => AddressingMode::Absolute(cursor.need_u16()?, Index::None),
+        ModeKind::AbsoluteX => AddressingMode::Absolute(cursor.need_u16()?, Index::X),
+        ModeKind::AbsoluteY => AddressingMode::Absolute(cursor.need_u16()?, Index::Y),
+        ModeKind::Indirect => AddressingMode::Indirect(cursor.need_u16()?),
+        ModeKind::IndexedIndirect => AddressingMode::IndexedIndirect(cursor.need_u8()?),
+        ModeKind::IndirectIndexed => AddressingMode::IndirectIndexed(cursor.need_u8()?),
+        ModeKind::ZeroPageIndirect => AddressingMode::ZeroPageIndirect(cursor.need_u8()?),
+        ModeKind::AbsoluteIndexedIndirect => {
+            AddressingMode::AbsoluteIndexedIndirect(cursor.need_u16()?)
+        }
+        ModeKind::ZeroPageRelative => {
+            AddressingMode::ZeroPageRelative(cursor.need_u8()?, cursor.need_i8()?)
+        }
+    };
+    for _ in 1 + opcode.mode.operand_len()..opcode.length {
+        cursor.need_u8()?; // padding, the byte following BRK
+    }
+    Ok(Some(Operation::new(
+        opcode.mnemonic,
+        mode,
+        Opcode::bit(operator),
+    )))
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{
+    Bus, Machine,
+    bus::ADDRESS_SPACE,
+    callstack::{Frame, FrameKind},
+    debugger::decode,
+    symbols::Symbols,
+};
+
+/// How the cycles spent outside of any call are named in the reports
+const TOP_LEVEL: &str = "(top level)";
+
+/// What ran at one address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub executions: u64,
+    /// Including the interrupt sequences and the cycles waiting for one
+    pub cycles: u64,
+}
+
+/// The cost of a subroutine or an interrupt handler
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Routine {
+    pub calls: u64,
+    /// Cycles spent in the routine and in what it called, counted once under recursion
+    pub inclusive: u64,
+    /// Cycles spent in the routine's own instructions
+    pub exclusive: u64,
+}
+
+/// A node of the call tree, one per distinct chain of calls
+#[derive(Debug)]
+struct Node {
+    /// The subroutine or handler entered, none for the top level
+    routine: Option<u16>,
+    children: HashMap<u16, usize>,
+    /// Cycles spent with this chain of calls on the shadow stack
+    cycles: u64,
+}
+
+impl Node {
+    fn new(routine: Option<u16>) -> Self {
+        Node {
+            routine,
+            children: HashMap::new(),
+            cycles: 0,
+        }
+    }
+}
+
+/// Counts the executions and cycles of every address and attributes the cycles to the
+/// routines on the shadow call stack, see [`Machine::record_profile`]
+#[derive(Debug)]
+pub struct Profiler {
+    addrs: Vec<Counts>,
+    calls: HashMap<u16, u64>,
+    /// The call tree, the top level first
+    nodes: Vec<Node>,
+    /// The routines of the current node, to skip the tree walk while the stack is unchanged
+    path: Vec<u16>,
+    node: usize,
+    cycles: u64,
+    instructions: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            addrs: vec![Counts::default(); ADDRESS_SPACE],
+            calls: HashMap::new(),
+            nodes: vec![Node::new(None)],
+            path: Vec::new(),
+            node: 0,
+            cycles: 0,
+            instructions: 0,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Cycles counted since profiling started
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn address(&self, addr: u16) -> Counts {
+        self.addrs[addr as usize]
+    }
+
+    /// The routines entered since profiling started, by address
+    pub fn routines(&self) -> HashMap<u16, Routine> {
+        let mut routines: HashMap<u16, Routine> = self
+            .calls
+            .iter()
+            .map(|(&addr, &calls)| {
+                let routine = Routine {
+                    calls,
+                    ..Routine::default()
+                };
+                (addr, routine)
+            })
+            .collect();
+        self.attribute(0, &mut Vec::new(), &mut routines);
+        routines
+    }
+
+    fn attribute(&self, node: usize, path: &mut Vec<u16>, routines: &mut HashMap<u16, Routine>) {
+        let node = &self.nodes[node];
+        if let Some(routine) = node.routine {
+            routines.entry(routine).or_default().exclusive += node.cycles;
+        }
+        for (i, routine) in path.iter().enumerate() {
+            if !path[..i].contains(routine) {
+                routines.entry(*routine).or_default().inclusive += node.cycles;
+            }
+        }
+        for (&routine, &child) in &node.children {
+            path.push(routine);
+            self.attribute(child, path, routines);
+            path.pop();
+        }
+    }
+
+    /// The cycles spent in each chain of calls, outermost routine first, top level
+    /// cycles under an empty chain
+    pub fn stacks(&self) -> Vec<(Vec<u16>, u64)> {
+        let mut stacks = Vec::new();
+        let mut pending = vec![(0, Vec::new())];
+        while let Some((node, path)) = pending.pop() {
+            let node = &self.nodes[node];
+            for (&routine, &child) in &node.children {
+                let mut path = path.clone();
+                path.push(routine);
+                pending.push((child, path));
+            }
+            if node.cycles > 0 {
+                stacks.push((path, node.cycles));
+            }
+        }
+        stacks.sort();
+        stacks
+    }
+
+    /// Move to the call tree node of the shadow stack before a step
+    pub(crate) fn enter(&mut self, frames: &[Frame]) {
+        if self.path.len() == frames.len()
+            && self.path.iter().zip(frames).all(|(&a, f)| a == f.target)
+        {
+            return;
+        }
+        self.path.clear();
+        self.node = 0;
+        for frame in frames {
+            let next = self.nodes.len();
+            let child = *self.nodes[self.node]
+                .children
+                .entry(frame.target)
+                .or_insert(next);
+            if child == next {
+                self.nodes.push(Node::new(Some(frame.target)));
+            }
+            self.path.push(frame.target);
+            self.node = child;
+        }
+    }
+
+    /// Count a step that started at `pc` with `depth` frames and took `cycles`,
+    /// `executed` when it ran an instruction rather than waiting for an interrupt
+    pub(crate) fn record(
+        &mut self,
+        pc: u16,
+        cycles: u64,
+        executed: bool,
+        depth: usize,
+        frames: &[Frame],
+    ) {
+        let entered = frames.last().filter(|_| frames.len() > depth);
+        if let Some(frame) = entered {
+            *self.calls.entry(frame.target).or_default() += 1;
+        }
+        self.cycles += cycles;
+        // the interrupt sequence belongs to the handler, the instructions to their caller
+        let (addr, executed) = match entered {
+            Some(frame) if matches!(frame.kind, FrameKind::Irq | FrameKind::Nmi) => {
+                self.enter(frames);
+                (frame.target, false)
+            }
+            _ => (pc, executed),
+        };
+        self.nodes[self.node].cycles += cycles;
+        let counts = &mut self.addrs[addr as usize];
+        counts.cycles += cycles;
+        if executed {
+            counts.executions += 1;
+            self.instructions += 1;
+        }
+    }
+
+    /// A report of the routines by inclusive cycles then of the addresses by cycles,
+    /// disassembled from the machine's memory
+    pub fn report<B: Bus>(&self, machine: &Machine<B>, out: &mut impl Write) -> io::Result<()> {
+        let symbols = machine.symbols();
+        writeln!(
+            out,
+            "{} cycles, {} instructions",
+            self.cycles, self.instructions
+        )?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:>8} {:>10} {:>6} {:>10} {:>6}  routine",
+            "calls", "inclusive", "%", "exclusive", "%"
+        )?;
+        let mut routines: Vec<_> = self.routines().into_iter().collect();
+        routines.sort_by_key(|&(addr, routine)| (u64::MAX - routine.inclusive, addr));
+        let top = self.nodes[0].cycles;
+        writeln!(
+            out,
+            "{:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%  {TOP_LEVEL}",
+            "",
+            self.cycles,
+            percent(self.cycles, self.cycles),
+            top,
+            percent(top, self.cycles),
+        )?;
+        for (addr, routine) in routines {
+            writeln!(
+                out,
+                "{:>8} {:>10} {:>5.1}% {:>10} {:>5.1}%  {}",
+                routine.calls,
+                routine.inclusive,
+                percent(routine.inclusive, self.cycles),
+                routine.exclusive,
+                percent(routine.exclusive, self.cycles),
+                routine_name(symbols, addr)
+            )?;
+        }
+        writeln!(out)?;
+        let mut addrs: Vec<_> = (0..=u16::MAX)
+            .map(|addr| (addr, self.address(addr)))
+            .filter(|(_, counts)| counts.cycles > 0)
+            .collect();
+        addrs.sort_by_key(|&(addr, counts)| (u64::MAX - counts.cycles, addr));
+        let labels: Vec<_> = addrs
+            .iter()
+            .map(|&(addr, _)| symbols.label(addr).unwrap_or_default())
+            .collect();
+        let width = labels.iter().map(String::len).max().unwrap_or_default();
+        writeln!(
+            out,
+            "addr {:>10} {:>10} {:>6}  {:width$}  instruction",
+            "executions", "cycles", "%", ""
+        )?;
+        for ((addr, counts), label) in addrs.into_iter().zip(labels) {
+            let instruction = match decode(machine, addr) {
+                (Some(op), _) => symbols.operation(&op, addr),
+                (None, _) => "???".to_string(),
+            };
+            writeln!(
+                out,
+                "{addr:04x} {:>10} {:>10} {:>5.1}%  {label:width$}  {instruction}",
+                counts.executions,
+                counts.cycles,
+                percent(counts.cycles, self.cycles)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// One `outer;inner cycles` line per chain of calls, the collapsed stack format read
+    /// by flamegraph.pl and inferno
+    pub fn write_collapsed(&self, symbols: &Symbols, out: &mut impl Write) -> io::Result<()> {
+        for (path, cycles) in self.stacks() {
+            let names: Vec<_> = path
+                .iter()
+                .map(|&addr| routine_name(symbols, addr))
+                .collect();
+            let stack = if names.is_empty() {
+                TOP_LEVEL.to_string()
+            } else {
+                names.join(";")
+            };
+            writeln!(out, "{stack} {cycles}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The label of a routine, or its address
+fn routine_name(symbols: &Symbols, addr: u16) -> String {
+    symbols.label(addr).unwrap_or_else(|| format!("{addr:04x}"))
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
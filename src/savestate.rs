@@ -0,0 +1,106 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+
+use crate::{Bus, Cpu, Flags, Machine, history::Snapshot};
+
+pub const MAGIC: &[u8; 8] = b"B6502SAV";
+/// Layout of version 1, little endian: the magic, the version (u16), the cpu (u8, 0 for
+/// the 6502 and 1 for the 65C02), A, X, Y, P and SP (u8 each), PC (u16), the cycle
+/// count (u64), the waiting, NMI line and NMI pending flags (u8 each), then the length
+/// of the bus state (u32) followed by the state itself
+pub const VERSION: u16 = 1;
+
+/// Write the state of `machine`, the undo log and the debugger settings are not saved
+pub fn save<B: Bus>(machine: &Machine<B>, out: &mut impl Write) -> anyhow::Result<()> {
+    let s = machine.snapshot();
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&[match machine.cpu() {
+        Cpu::Nmos => 0,
+        Cpu::Cmos => 1,
+    }])?;
+    out.write_all(&[s.acc, s.x, s.y, s.flags.bits(), s.sp as u8])?;
+    out.write_all(&(s.pc as u16).to_le_bytes())?;
+    out.write_all(&s.cycles.to_le_bytes())?;
+    out.write_all(&[s.waiting as u8, s.nmi_line as u8, s.nmi_pending as u8])?;
+    let bus = machine.bus().save_state();
+    out.write_all(&(bus.len() as u32).to_le_bytes())?;
+    out.write_all(&bus)?;
+    Ok(())
+}
+
+/// Restore a state written by [`save`] into a machine with the same cpu and memory map
+pub fn load<B: Bus>(machine: &mut Machine<B>, input: &mut impl Read) -> anyhow::Result<()> {
+    let mut header = [0; 33];
+    input
+        .read_exact(&mut header)
+        .context("truncated save state")?;
+    let (magic, header) = header.split_at(8);
+    if magic != MAGIC {
+        bail!("not a b6502 save state");
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+    let version = u16_at(0);
+    if version != VERSION {
+        bail!("unsupported save state version {version}, expected {VERSION}");
+    }
+    let cpu = match header[2] {
+        0 => Cpu::Nmos,
+        1 => Cpu::Cmos,
+        other => bail!("unknown cpu {other} in save state"),
+    };
+    if cpu != machine.cpu() {
+        bail!("the state was saved from a {cpu}, run with --cpu {cpu}");
+    }
+    let [acc, x, y, p, sp] = [3, 4, 5, 6, 7].map(|i| header[i]);
+    let mut cycles = [0; 8];
+    cycles.copy_from_slice(&header[10..18]);
+    let mut len = [0; 4];
+    len.copy_from_slice(&header[21..25]);
+    let mut bus = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut bus).context("truncated save state")?;
+    machine
+        .bus_mut()
+        .load_state(&bus)
+        .context("the save state doesn't match this memory map")?;
+    let pc = u16_at(8) as usize;
+    machine.restore(Snapshot {
+        acc,
+        x,
+        y,
+        flags: Flags::from_bits_retain(p),
+        sp: sp as usize,
+        pc,
+        bpc: pc,
+        cycles: u64::from_le_bytes(cycles),
+        waiting: header[18] != 0,
+        nmi_line: header[19] != 0,
+        nmi_pending: header[20] != 0,
+    });
+    // the calls and the steps recorded before the load don't apply to the restored state
+    machine.call_stack_mut().clear();
+    if let Some(history) = machine.history() {
+        machine.record_history(history.capacity());
+    }
+    Ok(())
+}
+
+pub fn save_file<B: Bus>(machine: &Machine<B>, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    save(machine, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn load_file<B: Bus>(machine: &mut Machine<B>, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    load(machine, &mut BufReader::new(file)).with_context(|| format!("loading {}", path.display()))
+}
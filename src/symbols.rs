@@ -0,0 +1,205 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::Context;
+
+use crate::{AddressingMode, Index, Operation};
+
+/// How far past a label an address is still shown relative to it
+const MAX_OFFSET: u16 = 0xFF;
+
+/// Names of addresses, read from the symbol files of assemblers and emulators
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    /// The first name given to each address, the one shown
+    names: BTreeMap<u16, String>,
+    addrs: HashMap<String, u16>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addrs.insert(name.to_string(), addr);
+    }
+
+    /// Add the symbols of a ca65/ld65 debug file (labels only), a VICE label file
+    /// (`al C:0600 .init`) or `name = $addr` lines, returns how many were read
+    pub fn parse(&mut self, text: &str) -> anyhow::Result<usize> {
+        let before = self.len();
+        if text.starts_with("version") {
+            self.parse_dbg(text)?;
+            return Ok(self.len() - before);
+        }
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split([';', '#']).next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = if let Some(label) = line.strip_prefix("al ") {
+                parse_vice(label)
+            } else {
+                parse_assignment(line)
+            };
+            let (name, addr) =
+                parsed.with_context(|| format!("line {}: bad symbol {line}", number + 1))?;
+            self.insert(name, addr);
+        }
+        Ok(self.len() - before)
+    }
+
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> anyhow::Result<usize> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        self.parse(&text)
+            .with_context(|| format!("loading symbols from {}", path.display()))
+    }
+
+    /// ld65 writes one record per line, `sym` records of type `lab` are the labels
+    fn parse_dbg(&mut self, text: &str) -> anyhow::Result<()> {
+        for (number, line) in text.lines().enumerate() {
+            let Some(fields) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+            let field = |key: &str| {
+                fields
+                    .split(',')
+                    .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+            };
+            if field("type") != Some("lab") {
+                continue;
+            }
+            let (Some(name), Some(value)) = (field("name"), field("val")) else {
+                continue;
+            };
+            let name = name.trim_matches('"');
+            let addr = value
+                .strip_prefix("0x")
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+                .with_context(|| format!("line {}: bad value {value}", number + 1))?;
+            self.insert(name, addr);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.addrs.get(name).copied()
+    }
+
+    /// The name of exactly this address
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// The closest name at or before `addr`, as `name` or `name+3`
+    pub fn label(&self, addr: u16) -> Option<String> {
+        let (&at, name) = self.names.range(..=addr).next_back()?;
+        match addr - at {
+            0 => Some(name.clone()),
+            offset if offset <= MAX_OFFSET => Some(format!("{name}+{offset:x}")),
+            _ => None,
+        }
+    }
+
+    /// An address typed by the user: a name, a name plus a hex offset (`updateSnake+3`),
+    /// or a hex number with an optional `$` or `0x` prefix. Names win over bare hex.
+    pub fn resolve(&self, s: &str) -> anyhow::Result<u16> {
+        if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+            return u16::from_str_radix(hex, 16).with_context(|| format!("bad address {s}"));
+        }
+        if let Some(addr) = self.get(s) {
+            return Ok(addr);
+        }
+        if let Some((name, offset)) = s.split_once('+')
+            && let Some(addr) = self.get(name)
+        {
+            let offset = self.resolve(offset)?;
+            return Ok(addr.wrapping_add(offset));
+        }
+        u16::from_str_radix(s, 16).with_context(|| format!("unknown symbol or bad address {s}"))
+    }
+
+    /// Disassemble `op` located at `addr`, with the addresses it refers to by name when
+    /// they have one. Immediate operands stay numbers.
+    pub fn operation(&self, op: &Operation, addr: u16) -> String {
+        use AddressingMode::*;
+        let label = |target: u16| self.label(target);
+        let branch =
+            |length: u16, offset: i8| label(addr.wrapping_add(length).wrapping_add(offset as u16));
+        let Some(mode) = op.mode() else {
+            return op.to_string();
+        };
+        let operand = match mode {
+            ZeroPage(n, index) => label(*n as u16).map(|l| format!("{l}{}", suffix(index))),
+            Absolute(n, index) => label(*n).map(|l| format!("{l}{}", suffix(index))),
+            Relative(offset) => branch(2, *offset),
+            Indirect(n) => label(*n).map(|l| format!("({l})")),
+            IndexedIndirect(n) => label(*n as u16).map(|l| format!("({l},X)")),
+            IndirectIndexed(n) => label(*n as u16).map(|l| format!("({l}),Y")),
+            ZeroPageIndirect(n) => label(*n as u16).map(|l| format!("({l})")),
+            AbsoluteIndexedIndirect(n) => label(*n).map(|l| format!("({l},X)")),
+            ZeroPageRelative(n, offset) => match (label(*n as u16), branch(3, *offset)) {
+                (None, None) => None,
+                (zp, target) => Some(format!(
+                    "{},{}",
+                    zp.unwrap_or_else(|| format!("${n:0>2x}")),
+                    target.unwrap_or_else(|| format!("${offset:0>2x}"))
+                )),
+            },
+            _ => None,
+        };
+        match operand {
+            Some(operand) => {
+                let bit = op.bit().map(|bit| bit.to_string()).unwrap_or_default();
+                format!("{}{bit} {operand}", op.mnemonic())
+            }
+            None => op.to_string(),
+        }
+    }
+}
+
+fn suffix(index: &Index) -> &'static str {
+    match index {
+        Index::None => "",
+        Index::X => ",X",
+        Index::Y => ",Y",
+    }
+}
+
+/// `C:0600 .init`, the memory space and the dot are optional
+fn parse_vice(label: &str) -> Option<(&str, u16)> {
+    let mut fields = label.split_whitespace();
+    let addr = fields.next()?;
+    let addr = addr.split_once(':').map_or(addr, |(_, addr)| addr);
+    let name = fields.next()?;
+    let name = name.strip_prefix('.').unwrap_or(name);
+    Some((name, u16::from_str_radix(addr, 16).ok()?))
+}
+
+/// `name = $addr`, with a `$` or `0x` hex value or a decimal one as in assembler sources
+fn parse_assignment(line: &str) -> Option<(&str, u16)> {
+    let (name, value) = line.split_once('=')?;
+    let (name, value) = (name.trim(), value.trim());
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    let addr = match value.strip_prefix('$').or_else(|| value.strip_prefix("0x")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+    Some((name, addr))
+}
@@ -0,0 +1,196 @@
+use std::io::BufRead;
+
+use crate::{AddressingMode, Bus, Flags, Machine, Operation, opcodes, parse_opcode};
+
+/// One line of a nestest style execution log, the state before an instruction runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceLine {
+    pub pc: u16,
+    pub bytes: Vec<u8>,
+    pub acc: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    /// Missing from some reference logs
+    pub cycles: Option<u64>,
+}
+
+impl TraceLine {
+    /// Capture the state of the machine before it executes the instruction at pc
+    pub fn capture<B: Bus>(machine: &Machine<B>) -> TraceLine {
+        let r = machine.registers();
+        let length = opcodes(machine.cpu())[machine.peek(r.pc) as usize].length;
+        TraceLine {
+            pc: r.pc,
+            bytes: (0..length as u16)
+                .map(|i| machine.peek(r.pc.wrapping_add(i)))
+                .collect(),
+            acc: r.acc,
+            x: r.x,
+            y: r.y,
+            // nestest shows bit 5 set, as pushed by PHP
+            p: (r.flags | Flags::UNUSED).bits(),
+            sp: r.sp,
+            cycles: Some(machine.cycles()),
+        }
+    }
+
+    /// Read a line of a reference log, the disassembly and the PPU columns are skipped
+    pub fn parse(line: &str) -> Option<TraceLine> {
+        let pc = u16::from_str_radix(line.get(0..4)?, 16).ok()?;
+        let registers = line.find(" A:")?;
+        let bytes = line
+            .get(6..15)
+            .unwrap_or_default()
+            .split_whitespace()
+            .map_while(|b| u8::from_str_radix(b, 16).ok())
+            .collect();
+        let mut trace = TraceLine {
+            pc,
+            bytes,
+            acc: 0,
+            x: 0,
+            y: 0,
+            p: 0,
+            sp: 0,
+            cycles: None,
+        };
+        let mut seen = 0;
+        for field in line[registers..].split_whitespace() {
+            let Some((name, value)) = field.split_once(':') else {
+                continue;
+            };
+            let register = match name {
+                "A" => &mut trace.acc,
+                "X" => &mut trace.x,
+                "Y" => &mut trace.y,
+                "P" => &mut trace.p,
+                "SP" => &mut trace.sp,
+                "CYC" => {
+                    trace.cycles = value.parse().ok();
+                    continue;
+                }
+                _ => continue,
+            };
+            *register = u8::from_str_radix(value, 16).ok()?;
+            seen += 1;
+        }
+        (seen == 5).then_some(trace)
+    }
+
+    /// Whether both lines show the same state, cycles are only compared when both have them
+    pub fn matches(&self, other: &TraceLine) -> bool {
+        let cycles = match (self.cycles, other.cycles) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        self.pc == other.pc
+            && self.bytes == other.bytes
+            && self.acc == other.acc
+            && self.x == other.x
+            && self.y == other.y
+            && self.p == other.p
+            && self.sp == other.sp
+            && cycles
+    }
+}
+
+/// Format the instruction at pc the nestest way: undocumented opcodes are marked
+/// with `*` and branches show their target
+pub fn disassemble<B: Bus>(machine: &Machine<B>, pc: u16) -> String {
+    let opcode = &opcodes(machine.cpu())[machine.peek(pc) as usize];
+    let mut cursor = (0..=2).map(|i| machine.peek(pc.wrapping_add(i)));
+    let text = match parse_opcode(&mut cursor, machine.cpu(), true) {
+        Ok(Some(op)) => match op.mode() {
+            Some(AddressingMode::Relative(offset)) => {
+                let target = pc
+                    .wrapping_add(opcode.length as u16)
+                    .wrapping_add_signed(*offset as i16);
+                format!("{} ${:0>4X}", op.mnemonic(), target)
+            }
+            _ => op.to_string().to_uppercase(),
+        },
+        _ => Operation::Halt.to_string(),
+    };
+    let marker = if opcode.undocumented { '*' } else { ' ' };
+    format!("{marker}{text}")
+}
+
+/// The nestest log line of the instruction at pc, without the PPU columns
+pub fn trace<B: Bus>(machine: &Machine<B>) -> String {
+    let line = TraceLine::capture(machine);
+    let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:0>2X}", b)).collect();
+    format!(
+        "{:0>4X}  {:<8} {:<33}A:{:0>2X} X:{:0>2X} Y:{:0>2X} P:{:0>2X} SP:{:0>2X} CYC:{}",
+        line.pc,
+        bytes.join(" "),
+        disassemble(machine, line.pc),
+        line.acc,
+        line.x,
+        line.y,
+        line.p,
+        line.sp,
+        line.cycles.unwrap_or_default()
+    )
+}
+
+/// First difference between the execution and a reference log
+#[derive(Debug)]
+pub struct Divergence {
+    /// 1-based line number in the reference log
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+pub enum Comparison {
+    Match,
+    Diverged(Divergence),
+    /// The reference log has no more lines
+    End,
+}
+
+/// Walks a reference log alongside the execution, one line per instruction
+pub struct LogComparator<R: BufRead> {
+    lines: std::io::Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> LogComparator<R> {
+    pub fn new(reference: R) -> Self {
+        LogComparator {
+            lines: reference.lines(),
+            line: 0,
+        }
+    }
+
+    /// Compare the state before the next instruction against the next reference line
+    pub fn check<B: Bus>(&mut self, machine: &Machine<B>) -> anyhow::Result<Comparison> {
+        let Some(expected) = self.next_line()? else {
+            return Ok(Comparison::End);
+        };
+        let Some(reference) = TraceLine::parse(&expected) else {
+            anyhow::bail!("line {}: not a trace line: {}", self.line, expected);
+        };
+        if reference.matches(&TraceLine::capture(machine)) {
+            return Ok(Comparison::Match);
+        }
+        Ok(Comparison::Diverged(Divergence {
+            line: self.line,
+            expected,
+            actual: trace(machine),
+        }))
+    }
+
+    fn next_line(&mut self) -> anyhow::Result<Option<String>> {
+        for line in self.lines.by_ref() {
+            self.line += 1;
+            let line = line?;
+            if !line.trim().is_empty() {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,167 @@
+use std::{fmt::Display, ops::RangeInclusive, str::FromStr};
+
+use anyhow::bail;
+
+/// The memory accesses a watchpoint triggers on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// A write storing a value different from the one in memory
+    Change,
+}
+
+impl FromStr for Access {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "r" | "read" => Ok(Access::Read),
+            "w" | "write" => Ok(Access::Write),
+            "c" | "change" => Ok(Access::Change),
+            _ => bail!("unknown access {s}, expected read, write or change"),
+        }
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Change => "change",
+        })
+    }
+}
+
+/// What happens when a watchpoint triggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Stop execution after the instruction
+    Pause,
+    /// Report the access and keep running
+    Log,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+    pub action: WatchAction,
+}
+
+impl Watchpoint {
+    fn triggers(&self, addr: u16, access: Access, old: u8, new: u8) -> bool {
+        self.range.contains(&addr)
+            && match self.access {
+                Access::Read => access == Access::Read,
+                Access::Write => access == Access::Write,
+                Access::Change => access == Access::Write && old != new,
+            }
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:0>4x}", self.range.start())?;
+        if self.range.start() != self.range.end() {
+            write!(f, "-{:0>4x}", self.range.end())?;
+        }
+        write!(f, " {}", self.access)?;
+        if self.action == WatchAction::Log {
+            write!(f, " log")?;
+        }
+        Ok(())
+    }
+}
+
+/// One access matching a watchpoint, `old` and `new` are equal for reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Index of the watchpoint in [`Watchpoints::list`]
+    pub index: usize,
+    /// Address of the instruction doing the access
+    pub pc: u16,
+    pub addr: u16,
+    pub access: Access,
+    pub old: u8,
+    pub new: u8,
+    pub action: WatchAction,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.access {
+            Access::Read => write!(
+                f,
+                "watch {}: {:0>4x} read {:0>4x} = {:0>2x}",
+                self.index, self.pc, self.addr, self.new
+            ),
+            Access::Write | Access::Change => write!(
+                f,
+                "watch {}: {:0>4x} wrote {:0>4x} {:0>2x} -> {:0>2x}",
+                self.index, self.pc, self.addr, self.old, self.new
+            ),
+        }
+    }
+}
+
+/// The watchpoints of a machine and the hits not yet collected
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    list: Vec<Option<Watchpoint>>,
+    hits: Vec<WatchHit>,
+}
+
+impl Watchpoints {
+    /// Add a watchpoint, returns its index
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        self.list.push(Some(watchpoint));
+        self.list.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Watchpoint> {
+        self.list.get_mut(index).and_then(Option::take)
+    }
+
+    /// The watchpoints still set, with their index
+    pub fn list(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.list
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| w.as_ref().map(|w| (i, w)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.iter().all(Option::is_none)
+    }
+
+    /// Take the hits recorded since the last call
+    pub fn take_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Cheap test done on every access before looking for a match
+    pub(crate) fn armed(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub(crate) fn check(&mut self, pc: u16, addr: u16, access: Access, old: u8, new: u8) {
+        for (index, watchpoint) in self.list.iter().enumerate() {
+            let Some(watchpoint) = watchpoint else {
+                continue;
+            };
+            if watchpoint.triggers(addr, access, old, new) {
+                self.hits.push(WatchHit {
+                    index,
+                    pc,
+                    addr,
+                    access,
+                    old,
+                    new,
+                    action: watchpoint.action,
+                });
+            }
+        }
+    }
+}
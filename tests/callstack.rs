@@ -0,0 +1,116 @@
+mod common;
+
+use b6502::{
+    Machine, Status,
+    callstack::{Divergence, FrameKind},
+    debugger::{Debugger, Flow},
+};
+
+fn machine(program: &[u8]) -> Machine {
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, program).unwrap();
+    machine
+}
+
+fn run_to_halt(machine: &mut Machine) -> Vec<Divergence> {
+    while let Status::Cont = machine.step().unwrap() {}
+    machine.call_stack_mut().take_divergences()
+}
+
+#[test]
+fn divergences() {
+    // 0600 JSR $0606, JAM, 0606 JSR $060a, RTS, 060a PLA, PLA, RTS
+    let mut unwinding = machine(&[
+        0x20, 0x06, 0x06, 0x02, 0x00, 0x00, 0x20, 0x0a, 0x06, 0x60, 0x68, 0x68, 0x60,
+    ]);
+    assert_eq!(
+        run_to_halt(&mut unwinding),
+        [Divergence::Unwound {
+            pc: 0x060c,
+            frames: 1
+        }]
+    );
+    assert_eq!(unwinding.call_stack().depth(), 0);
+
+    // 0600 LDA #$06, PHA, LDA #$0a, PHA, RTS, 060b JAM
+    let mut jump = machine(&[
+        0xa9, 0x06, 0x48, 0xa9, 0x0a, 0x48, 0x60, 0x00, 0x00, 0x00, 0x00, 0x02,
+    ]);
+    assert_eq!(
+        run_to_halt(&mut jump),
+        [Divergence::Unmatched {
+            pc: 0x0606,
+            to: 0x060b
+        }]
+    );
+
+    // 0600 JSR $0604, JAM, 0604 PLA, PLA, LDA #$06, PHA, LDA #$0c, PHA, RTS, 060d JAM
+    let mut redirect = machine(&[
+        0x20, 0x04, 0x06, 0x02, 0x68, 0x68, 0xa9, 0x06, 0x48, 0xa9, 0x0c, 0x48, 0x60, 0x02,
+    ]);
+    assert_eq!(
+        run_to_halt(&mut redirect),
+        [Divergence::Redirected {
+            pc: 0x060c,
+            to: 0x060d,
+            expected: 0x0603
+        }]
+    );
+
+    // 0600 BRK, padding, JAM, with the handler at 0700 returning with RTI
+    let mut interrupt = machine(&[0x00, 0x00, 0x02]);
+    interrupt.poke(0xfffe, 0x00);
+    interrupt.poke(0xffff, 0x07);
+    interrupt.poke(0x0700, 0x40);
+    interrupt.step().unwrap();
+    let frame = interrupt.call_stack().frames()[0];
+    assert_eq!(frame.kind, FrameKind::Brk);
+    assert_eq!(
+        (frame.site, frame.target, frame.ret),
+        (0x0600, 0x0700, 0x0602)
+    );
+    assert_eq!(run_to_halt(&mut interrupt), []);
+    assert_eq!(interrupt.call_stack().depth(), 0);
+}
+
+#[test]
+fn backtrace_and_undo() {
+    // 0600 JSR $0606, JAM, 0606 LDA #$42, PHA, JSR $060d, 060c RTS, 060d INY, RTS
+    let mut machine = machine(&[
+        0x20, 0x06, 0x06, 0x02, 0x00, 0x00, 0xa9, 0x42, 0x48, 0x20, 0x0d, 0x06, 0x60, 0xc8, 0x60,
+    ]);
+    machine.record_history(100);
+    machine
+        .symbols_mut()
+        .parse("outer = $0606\ninner = $060d\n")
+        .unwrap();
+    let mut debugger = Debugger::new();
+    let mut run = |machine: &mut Machine, line: &str| {
+        let mut out = Vec::new();
+        let flow = debugger.command(machine, line, &mut out).unwrap();
+        assert_eq!(flow, Flow::Continue);
+        String::from_utf8(out).unwrap()
+    };
+    run(&mut machine, "until inner");
+    assert_eq!(
+        run(&mut machine, "bt"),
+        "#0  060d <inner>\n\
+         #1  0609 <outer+3>  jsr 060d <inner> -> 060c  01fb: 0b 06 42\n\
+         #2  0600  jsr 0606 <outer> -> 0603  01fe: 02 06\n"
+    );
+    machine.poke(0x01fb, 0x20);
+    assert!(
+        run(&mut machine, "bt").contains("warning: the stack holds 0620 at 01fb, expected 060b")
+    );
+    machine.poke(0x01fb, 0x0b);
+
+    run(&mut machine, "back 2");
+    assert_eq!(machine.call_stack().depth(), 1);
+    run(&mut machine, "step 2");
+    assert_eq!(machine.call_stack().depth(), 2);
+    run(&mut machine, "step 2");
+    assert_eq!(machine.registers().pc, 0x060c);
+    assert_eq!(machine.call_stack().depth(), 1);
+    run(&mut machine, "back");
+    assert_eq!(machine.call_stack().frames()[1].target, 0x060d);
+}
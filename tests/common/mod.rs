@@ -0,0 +1,30 @@
+//! Helpers shared by the integration tests, not all of them use every one
+#![allow(dead_code)]
+
+use std::{env, fs, path::PathBuf};
+
+use b6502::{
+    Machine, MemoryMap,
+    bus::{ADDRESS_SPACE, Ram},
+};
+
+/// A test binary from `tests/roms` or the directory named by `B6502_ROMS`, panicking when
+/// it is missing so an ignored test run on purpose can't pass without it
+pub fn rom(name: &str) -> Vec<u8> {
+    let dir = env::var_os("B6502_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"));
+    fs::read(dir.join(name)).unwrap_or_else(|e| panic!("{name} in {}: {e}", dir.display()))
+}
+
+/// RAM over the whole address space
+pub fn ram() -> MemoryMap {
+    let mut bus = MemoryMap::new();
+    bus.map(0x0000..=0xFFFF, Ram::new(ADDRESS_SPACE));
+    bus
+}
+
+/// A machine with RAM over the whole address space
+pub fn machine() -> Machine {
+    Machine::builder().build(ram())
+}
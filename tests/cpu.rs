@@ -0,0 +1,242 @@
+use b6502::{Bus, Cpu, Flags, Machine, Registers, Status, bus::ADDRESS_SPACE};
+
+/// RAM over the whole address space with interrupt lines the test drives
+struct Lines {
+    ram: Vec<u8>,
+    irq: bool,
+    nmi: bool,
+}
+
+impl Bus for Lines {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize] = value;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn nmi(&self) -> bool {
+        self.nmi
+    }
+}
+
+fn machine(cpu: Cpu, program: &[u8]) -> Machine<Lines> {
+    let bus = Lines {
+        ram: vec![0; ADDRESS_SPACE],
+        irq: false,
+        nmi: false,
+    };
+    let mut machine = Machine::builder().cpu(cpu).build(bus);
+    machine.load_jmp(0x0600, program).unwrap();
+    machine
+}
+
+fn set_flags(machine: &mut Machine<Lines>, flags: Flags) {
+    let registers = machine.registers();
+    machine.set_registers(Registers { flags, ..registers });
+}
+
+/// The return address and the status pushed by an interrupt, from the top of the stack
+fn pushed(machine: &Machine<Lines>) -> (u16, Flags) {
+    let sp = machine.registers().sp as u16;
+    let status = Flags::from_bits_retain(machine.peek(0x0101 + sp));
+    let ret = u16::from_le_bytes([machine.peek(0x0102 + sp), machine.peek(0x0103 + sp)]);
+    (ret, status)
+}
+
+#[test]
+fn halt_stays_halted() {
+    // INX, then JAM on the NMOS 6502 and STP on the 65C02, followed by INX
+    for (cpu, halt) in [(Cpu::Nmos, 0x02), (Cpu::Cmos, 0xdb)] {
+        let mut machine = machine(cpu, &[0xe8, halt, 0xe8]);
+        assert_eq!(machine.step().unwrap(), Status::Cont);
+        for _ in 0..2 {
+            assert_eq!(machine.step().unwrap(), Status::Halt);
+            assert_eq!(machine.registers().pc, 0x0601);
+        }
+        assert_eq!(machine.registers().x, 1);
+    }
+}
+
+#[test]
+fn irq_is_masked_by_i() {
+    // NOP, NOP, with a NOP handler at $0700
+    let mut machine = machine(Cpu::Nmos, &[0xea, 0xea]);
+    machine.poke(0xfffe, 0x00);
+    machine.poke(0xffff, 0x07);
+    machine.poke(0x0700, 0xea);
+    machine.bus_mut().irq = true;
+    set_flags(&mut machine, Flags::INTERRUPT_DISABLE);
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0601);
+
+    set_flags(&mut machine, Flags::CARRY);
+    let cycles = machine.cycles();
+    machine.step().unwrap();
+    let registers = machine.registers();
+    assert_eq!(registers.pc, 0x0700);
+    assert_eq!(registers.sp, 0xfc);
+    assert!(registers.flags.contains(Flags::INTERRUPT_DISABLE));
+    assert_eq!(machine.cycles() - cycles, 7);
+    // IRQ pushes B clear and bit 5 set, the address of the instruction it came before
+    let (ret, status) = pushed(&machine);
+    assert_eq!(ret, 0x0601);
+    assert_eq!(status, Flags::CARRY | Flags::UNUSED);
+
+    // I is now set, the handler runs while the line stays asserted
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0701);
+}
+
+#[test]
+fn brk_pushes_b() {
+    // BRK, its padding byte
+    for cpu in [Cpu::Nmos, Cpu::Cmos] {
+        let mut machine = machine(cpu, &[0x00, 0xff]);
+        machine.poke(0xfffe, 0x00);
+        machine.poke(0xffff, 0x07);
+        set_flags(&mut machine, Flags::DECIMAL);
+        let cycles = machine.cycles();
+        machine.step().unwrap();
+        assert_eq!(machine.registers().pc, 0x0700);
+        assert_eq!(machine.cycles() - cycles, 7);
+        let (ret, status) = pushed(&machine);
+        assert_eq!(ret, 0x0602);
+        assert_eq!(status, Flags::DECIMAL | Flags::BREAK | Flags::UNUSED);
+        // the 65C02 clears D on interrupts, the NMOS 6502 leaves it
+        let decimal = machine.registers().flags.contains(Flags::DECIMAL);
+        assert_eq!(decimal, cpu == Cpu::Nmos);
+    }
+}
+
+#[test]
+fn nmi_is_edge_triggered() {
+    let mut machine = machine(Cpu::Nmos, &[0xea]);
+    machine.poke(0xfffa, 0x00);
+    machine.poke(0xfffb, 0x08);
+    // the handler at $0800 is NOPs then RTI
+    machine.poke(0x0800, 0xea);
+    machine.poke(0x0801, 0x40);
+    set_flags(&mut machine, Flags::INTERRUPT_DISABLE);
+    machine.bus_mut().nmi = true;
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0800, "NMI ignores I");
+    let (ret, status) = pushed(&machine);
+    assert_eq!(ret, 0x0600);
+    assert_eq!(status, Flags::INTERRUPT_DISABLE | Flags::UNUSED);
+
+    // held asserted, the line doesn't interrupt again
+    machine.step().unwrap();
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0600);
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0601);
+
+    // a new edge does
+    machine.bus_mut().nmi = false;
+    machine.step().unwrap();
+    machine.bus_mut().nmi = true;
+    machine.step().unwrap();
+    assert_eq!(machine.registers().pc, 0x0800);
+}
+
+#[test]
+fn reset_sequence() {
+    for cpu in [Cpu::Nmos, Cpu::Cmos] {
+        let mut machine = machine(cpu, &[]);
+        machine.poke(0xfffc, 0x00);
+        machine.poke(0xfffd, 0x09);
+        machine.set_registers(Registers {
+            acc: 1,
+            x: 2,
+            y: 3,
+            sp: 0xff,
+            pc: 0x0600,
+            flags: Flags::DECIMAL,
+        });
+        let cycles = machine.cycles();
+        machine.reset().unwrap();
+        let registers = machine.registers();
+        assert_eq!(machine.cycles() - cycles, 7);
+        assert_eq!(registers.pc, 0x0900);
+        // three suppressed pushes: SP moves, nothing is written
+        assert_eq!(registers.sp, 0xfc);
+        assert_eq!(machine.peek(0x01ff), 0);
+        assert_eq!((registers.acc, registers.x, registers.y), (1, 2, 3));
+        assert!(registers.flags.contains(Flags::INTERRUPT_DISABLE));
+        let decimal = registers.flags.contains(Flags::DECIMAL);
+        assert_eq!(decimal, cpu == Cpu::Nmos);
+    }
+}
+
+#[test]
+fn zero_page_wraps() {
+    // LDA $80,X with X = $90 reads $0010, not $0110
+    let mut indexed = machine(Cpu::Nmos, &[0xb5, 0x80]);
+    indexed.poke(0x0010, 0x42);
+    indexed.poke(0x0110, 0x99);
+    let registers = indexed.registers();
+    indexed.set_registers(Registers {
+        x: 0x90,
+        ..registers
+    });
+    indexed.step().unwrap();
+    assert_eq!(indexed.registers().acc, 0x42);
+
+    // LDA ($ff),Y takes the pointer high byte from $00, LDA ($f0,X) with X = $0f too
+    for (program, x, y) in [([0xb1, 0xff], 0, 1), ([0xa1, 0xf0], 0x0f, 0)] {
+        let mut machine = machine(Cpu::Nmos, &program);
+        machine.poke(0x00ff, 0x00);
+        machine.poke(0x0000, 0x03);
+        machine.poke(0x0100, 0x04);
+        machine.poke(0x0300 + y as u16, 0x42);
+        let registers = machine.registers();
+        machine.set_registers(Registers { x, y, ..registers });
+        machine.step().unwrap();
+        assert_eq!(machine.registers().acc, 0x42, "{program:02x?}");
+    }
+}
+
+#[test]
+fn stack_wraps() {
+    // PHA with SP = $00 writes $0100 and wraps to $ff
+    let mut machine = machine(Cpu::Nmos, &[0x48, 0x68]);
+    machine.set_registers(Registers {
+        acc: 0x42,
+        x: 0,
+        y: 0,
+        sp: 0x00,
+        pc: 0x0600,
+        flags: Flags::empty(),
+    });
+    machine.step().unwrap();
+    assert_eq!(machine.peek(0x0100), 0x42);
+    assert_eq!(machine.registers().sp, 0xff);
+    // PLA with SP = $ff wraps to $00 and reads $0100
+    machine.poke(0x0100, 0x24);
+    machine.step().unwrap();
+    assert_eq!(machine.registers().acc, 0x24);
+    assert_eq!(machine.registers().sp, 0x00);
+}
+
+#[test]
+fn jmp_indirect_page_bug() {
+    // JMP ($02ff): the NMOS 6502 takes the high byte from $0200, the 65C02 from $0300
+    for (cpu, target) in [(Cpu::Nmos, 0x0834), (Cpu::Cmos, 0x0934)] {
+        let mut machine = machine(cpu, &[0x6c, 0xff, 0x02]);
+        machine.poke(0x02ff, 0x34);
+        machine.poke(0x0200, 0x08);
+        machine.poke(0x0300, 0x09);
+        machine.step().unwrap();
+        assert_eq!(machine.registers().pc, target);
+    }
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use std::io::Cursor;
+
+use b6502::{Machine, dap};
+use serde_json::{Value, json};
+
+/// Play the requests, each as (command, arguments), and return the messages sent back
+fn session(program: &'static [u8], requests: &[(&str, Value)]) -> Vec<Value> {
+    let mut input = Vec::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let request = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        input.extend(format!("Content-Length: {}\r\n\r\n{request}", request.len()).bytes());
+    }
+    let mut output = Vec::new();
+    dap::serve(Cursor::new(input), &mut output, |_| {
+        // strict, so that an undocumented opcode is an error
+        let mut machine = Machine::builder().undocumented(false).build(common::ram());
+        machine.load_jmp(0x0600, program)?;
+        Ok(machine)
+    })
+    .unwrap();
+    let mut output = output.as_slice();
+    let mut messages = Vec::new();
+    while !output.is_empty() {
+        let header = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let length: usize = std::str::from_utf8(&output[16..header])
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = &output[header + 4..header + 4 + length];
+        messages.push(serde_json::from_slice(body).unwrap());
+        output = &output[header + 4 + length..];
+    }
+    messages
+}
+
+fn response(messages: &[Value], request_seq: u64) -> &Value {
+    messages
+        .iter()
+        .find(|m| m["type"] == "response" && m["request_seq"] == request_seq)
+        .unwrap()
+}
+
+fn stops(messages: &[Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .filter(|m| m["event"] == "stopped")
+        .map(|m| m["body"]["reason"].as_str().unwrap())
+        .collect()
+}
+
+// 0600 JSR $0606, INX, JAM, 0604 NOP, NOP, 0606 LDA #$42, STA $10, RTS
+const CALL: [u8; 11] = [
+    0x20, 0x06, 0x06, 0xe8, 0x02, 0xea, 0xa9, 0x42, 0x85, 0x10, 0x60,
+];
+
+#[test]
+fn inspect_and_step() {
+    let messages = session(
+        &CALL,
+        &[
+            ("initialize", json!({ "adapterID": "b6502" })),
+            ("launch", json!({})),
+            ("configurationDone", json!({})),
+            ("stepIn", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            (
+                "readMemory",
+                json!({ "memoryReference": "0x0010", "count": 1 }),
+            ),
+            (
+                "setVariable",
+                json!({ "variablesReference": 2, "name": "C", "value": "1" }),
+            ),
+            (
+                "writeMemory",
+                json!({ "memoryReference": "0x0010", "data": "AQI=" }),
+            ),
+            ("evaluate", json!({ "expression": "c + word[$10]" })),
+            (
+                "disassemble",
+                json!({ "memoryReference": "0x0606", "instructionOffset": -1, "instructionCount": 3 }),
+            ),
+            ("next", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ],
+    );
+    assert!(response(&messages, 1)["body"]["supportsDisassembleRequest"] == true);
+    assert_eq!(messages[2]["event"], "initialized");
+    assert_eq!(
+        stops(&messages),
+        ["entry", "step", "step", "step", "exception"]
+    );
+    let frame = &response(&messages, 5)["body"]["stackFrames"][0];
+    assert_eq!(frame["name"], "LDA #$42");
+    assert_eq!(frame["instructionPointerReference"], "0x0606");
+    let registers = &response(&messages, 7)["body"]["variables"];
+    assert_eq!(
+        registers[0],
+        json!({ "name": "A", "value": "$42", "variablesReference": 0 })
+    );
+    assert_eq!(registers[4]["value"], "$0603");
+    assert_eq!(response(&messages, 8)["body"]["data"], "Qg==");
+    assert_eq!(response(&messages, 11)["body"]["result"], "514 ($202)");
+    let instructions = &response(&messages, 12)["body"]["instructions"];
+    assert_eq!(instructions[0]["instruction"], "NOP");
+    assert_eq!(instructions[1]["address"], "0x0606");
+    assert_eq!(instructions[2]["instructionBytes"], "85 10");
+    assert!(response(&messages, 15)["success"] == true);
+}
+
+// 0600 LDX #$00, 0602 INX, JMP $0602
+const LOOP: [u8; 6] = [0xa2, 0x00, 0xe8, 0x4c, 0x02, 0x06];
+
+#[test]
+fn breakpoints_and_pause() {
+    let messages = session(
+        &LOOP,
+        &[
+            ("initialize", json!({})),
+            ("launch", json!({ "stopOnEntry": false })),
+            (
+                "setInstructionBreakpoints",
+                json!({ "breakpoints": [{ "instructionReference": "0x0602", "offset": 1 }] }),
+            ),
+            ("setBreakpoints", json!({ "breakpoints": [{ "line": 3 }] })),
+            ("configurationDone", json!({})),
+            ("continue", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("setInstructionBreakpoints", json!({ "breakpoints": [] })),
+            ("continue", json!({ "threadId": 1 })),
+            ("pause", json!({ "threadId": 1 })),
+            ("bogus", json!({})),
+        ],
+    );
+    let breakpoint = &response(&messages, 3)["body"]["breakpoints"][0];
+    assert_eq!(breakpoint["verified"], true);
+    assert_eq!(breakpoint["instructionReference"], "0x0603");
+    assert_eq!(
+        response(&messages, 4)["body"]["breakpoints"][0]["verified"],
+        false
+    );
+    assert_eq!(stops(&messages), ["breakpoint", "breakpoint", "pause"]);
+    assert_eq!(
+        response(&messages, 7)["body"]["variables"][1]["value"],
+        "$02"
+    );
+    assert!(response(&messages, 10)["success"] == true);
+    assert!(response(&messages, 11)["success"] == false);
+}
+
+#[test]
+fn target_errors() {
+    // INX, then LAX $10 which strict decoding rejects
+    let messages = session(
+        &[0xe8, 0xa7, 0x10],
+        &[
+            ("initialize", json!({ "adapterID": "b6502" })),
+            ("launch", json!({ "stopOnEntry": false })),
+            ("configurationDone", json!({})),
+            ("threads", json!({})),
+        ],
+    );
+    assert_eq!(stops(&messages), ["exception"]);
+    let output = messages.iter().find(|m| m["event"] == "output").unwrap();
+    assert!(
+        output["body"]["output"]
+            .as_str()
+            .unwrap()
+            .contains("unknown operator a7")
+    );
+    assert!(response(&messages, 4)["success"] == true);
+}
+
+#[test]
+fn disassemble_before_an_overlap() {
+    // NOP, LDA $a910, then LDA #$42 from its last byte
+    let messages = session(
+        &[0xea, 0xad, 0x10, 0xa9, 0x42],
+        &[
+            ("initialize", json!({})),
+            ("launch", json!({})),
+            (
+                "disassemble",
+                json!({ "memoryReference": "0x0603", "instructionOffset": -1, "instructionCount": 2 }),
+            ),
+        ],
+    );
+    let instructions = &response(&messages, 3)["body"]["instructions"];
+    assert_eq!(instructions[0]["address"], "0x0600");
+    assert_eq!(instructions[0]["instruction"], "NOP");
+    assert_eq!(instructions[1]["address"], "0x0603");
+    assert_eq!(instructions[1]["instruction"], "LDA #$42");
+}
+
+#[test]
+fn step_out_across_the_stack_wrap() {
+    let messages = session(
+        &CALL,
+        &[
+            ("initialize", json!({})),
+            ("launch", json!({})),
+            ("configurationDone", json!({})),
+            (
+                "setVariable",
+                json!({ "variablesReference": 1, "name": "SP", "value": "$01" }),
+            ),
+            ("stepIn", json!({ "threadId": 1 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+        ],
+    );
+    assert_eq!(stops(&messages), ["entry", "step", "step"]);
+    let registers = &response(&messages, 7)["body"]["variables"];
+    assert_eq!(registers[3]["value"], "$01");
+    assert_eq!(registers[4]["value"], "$0603");
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use b6502::{
+    Machine, Registers,
+    debugger::{Debugger, Flow},
+    expr::Expr,
+};
+
+// 0600 JSR $0607, LDX #$01, JAM, 0607 LDA #$42, INY, RTS
+const PROGRAM: [u8; 11] = [
+    0x20, 0x07, 0x06, 0xa2, 0x01, 0x02, 0x00, 0xa9, 0x42, 0xc8, 0x60,
+];
+
+fn machine() -> Machine {
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, &PROGRAM).unwrap();
+    machine
+}
+
+fn run(debugger: &mut Debugger, machine: &mut Machine, line: &str) -> String {
+    let mut out = Vec::new();
+    assert_eq!(
+        debugger.command(machine, line, &mut out).unwrap(),
+        Flow::Continue
+    );
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn step_over_and_out() {
+    let mut machine = machine();
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut machine, "next");
+    assert_eq!(machine.registers().pc, 0x0603);
+    assert_eq!(machine.registers().acc, 0x42);
+
+    let mut machine = self::machine();
+    run(&mut debugger, &mut machine, "step 2");
+    assert_eq!(machine.registers().pc, 0x0609);
+    run(&mut debugger, &mut machine, "finish");
+    assert_eq!(machine.registers().pc, 0x0603);
+    assert_eq!(machine.registers().y, 1);
+
+    // JSR with SP = $01 pushes the return address to $0101 and $0100, SP wraps to $ff
+    let mut wrapping = self::machine();
+    let registers = wrapping.registers();
+    wrapping.set_registers(Registers {
+        sp: 0x01,
+        ..registers
+    });
+    run(&mut debugger, &mut wrapping, "step");
+    assert_eq!(wrapping.registers().sp, 0xff);
+    let out = run(&mut debugger, &mut wrapping, "bt");
+    assert!(!out.contains("warning"), "{out}");
+    run(&mut debugger, &mut wrapping, "finish");
+    assert_eq!(wrapping.registers().pc, 0x0603);
+    assert_eq!(wrapping.registers().sp, 0x01);
+}
+
+#[test]
+fn breakpoints_and_editing() {
+    let mut machine = machine();
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut machine, "break $0609");
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(out.starts_with("breakpoint 0\n"), "{out}");
+    assert!(out.contains("> 0609  c8        INY"), "{out}");
+
+    run(&mut debugger, &mut machine, "set x 7f");
+    run(&mut debugger, &mut machine, "set c 1");
+    assert_eq!(machine.registers().x, 0x7f);
+    run(&mut debugger, &mut machine, "fill 10 13 aa");
+    run(&mut debugger, &mut machine, "poke 12 1 2");
+    assert_eq!(
+        [0x10, 0x11, 0x12, 0x13, 0x14].map(|addr| machine.peek(addr)),
+        [0xaa, 0xaa, 0x01, 0x02, 0x00]
+    );
+
+    let mut out = Vec::new();
+    assert!(debugger.command(&mut machine, "set q 1", &mut out).is_err());
+    assert_eq!(
+        debugger.command(&mut machine, "quit", &mut out).unwrap(),
+        Flow::Quit
+    );
+}
+
+#[test]
+fn watchpoints() {
+    let mut machine = common::machine();
+    // LDA #$05, STA $10, STA $10, INC $10, JAM
+    let program = [0xa9, 0x05, 0x85, 0x10, 0x85, 0x10, 0xe6, 0x10, 0x02];
+    machine.load_jmp(0x0600, &program).unwrap();
+    let mut debugger = Debugger::new();
+
+    run(&mut debugger, &mut machine, "watch 10 change");
+    run(&mut debugger, &mut machine, "watch 0f-10 read log");
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(
+        out.starts_with("watch 0: 0602 wrote 0010 00 -> 05\n"),
+        "{out}"
+    );
+    assert_eq!(machine.registers().pc, 0x0604);
+
+    // the second store leaves the value alone, the INC reads then changes it
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(
+        out.starts_with("watch 1: 0606 read 0010 = 05\nwatch 0: 0606 wrote 0010 05 -> 06\n"),
+        "{out}"
+    );
+    run(&mut debugger, &mut machine, "unwatch 0");
+    let out = run(&mut debugger, &mut machine, "watches");
+    assert_eq!(out, "watch 1: 000f-0010 read log\n");
+}
+
+#[test]
+fn expressions() {
+    let mut machine = machine();
+    machine.poke(0x02, 0x34);
+    machine.poke(0x03, 0x12);
+    let eval = |s: &str| s.parse::<Expr>().unwrap().eval(&machine).unwrap();
+    assert_eq!(eval("pc == $0600 && mem[$02] & 4"), 1);
+    assert_eq!(eval("word[2] == 0x1234"), 1);
+    assert_eq!(eval("1 + 2 * 3 << 1"), 14);
+    assert_eq!(eval("!(sp - 255) || c"), 1);
+    assert_eq!(eval("-1 & ~$0f"), -16);
+    for bad in ["", "a ==", "mem[2", "a = 1", "q", "1 / 0"] {
+        let parsed = bad.parse::<Expr>();
+        assert!(
+            parsed.is_err() || parsed.unwrap().eval(&machine).is_err(),
+            "{bad}"
+        );
+    }
+}
+
+#[test]
+fn conditional_breakpoints() {
+    // LDX #$00, INX, BNE -3, JAM
+    let program = [0xa2, 0x00, 0xe8, 0xd0, 0xfd, 0x02];
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, &program).unwrap();
+    let mut debugger = Debugger::new();
+
+    run(&mut debugger, &mut machine, "break 0602 if x & 1");
+    run(&mut debugger, &mut machine, "ignore 0 2");
+    run(&mut debugger, &mut machine, "break if x == $80 && n");
+    run(&mut debugger, &mut machine, "action 1 log");
+    run(&mut debugger, &mut machine, "continue");
+    assert_eq!(machine.registers().x, 5);
+
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(out.starts_with("breakpoint 0\n"), "{out}");
+    assert_eq!(machine.registers().x, 7);
+
+    run(&mut debugger, &mut machine, "delete 0");
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(
+        out.starts_with("breakpoint 1 at 0603: pc:0603 acc:00 x:80"),
+        "{out}"
+    );
+    assert!(out.contains("\nbreakpoint 1 at 0602: "), "{out}");
+    assert!(out.contains("\nhalted\n"), "{out}");
+
+    let out = run(&mut debugger, &mut machine, "breaks");
+    assert_eq!(out, "breakpoint 1: * if x == $80 && n log, hit 2 times\n");
+}
+
+#[test]
+fn step_back() {
+    // LDX #$00, 0602 INX, STX $10, CPX #$05, BNE -7, LDA #$ff, STA $11, JAM
+    let program = [
+        0xa2, 0x00, 0xe8, 0x86, 0x10, 0xe0, 0x05, 0xd0, 0xf9, 0xa9, 0xff, 0x85, 0x11, 0x02,
+    ];
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, &program).unwrap();
+    let mut debugger = Debugger::new();
+
+    let mut out = Vec::new();
+    assert!(debugger.command(&mut machine, "back", &mut out).is_err());
+    run(&mut debugger, &mut machine, "record 100");
+    run(&mut debugger, &mut machine, "break 0602 if x == 2");
+    run(&mut debugger, &mut machine, "continue");
+    let cycles = machine.cycles();
+    run(&mut debugger, &mut machine, "delete 0");
+    let out = run(&mut debugger, &mut machine, "continue");
+    assert!(out.contains("halted"), "{out}");
+    assert_eq!((machine.peek(0x10), machine.peek(0x11)), (5, 0xff));
+
+    run(&mut debugger, &mut machine, "back 2");
+    assert_eq!(machine.registers().pc, 0x060b);
+    assert_eq!(machine.peek(0x11), 0x00);
+
+    // the last store to $10 wrote 5 over 4
+    run(&mut debugger, &mut machine, "rwatch 10");
+    assert_eq!(machine.registers().pc, 0x0603);
+    assert_eq!((machine.registers().x, machine.peek(0x10)), (5, 4));
+
+    run(&mut debugger, &mut machine, "break 0602 if x == 2");
+    run(&mut debugger, &mut machine, "rcontinue");
+    assert_eq!(machine.registers().pc, 0x0602);
+    assert_eq!(machine.registers().x, 2);
+    assert_eq!(machine.cycles(), cycles);
+    assert_eq!(machine.peek(0x10), 2);
+
+    let out = run(&mut debugger, &mut machine, "back 100");
+    assert!(out.starts_with("start of the recorded history\n"), "{out}");
+    assert_eq!(machine.registers().pc, 0x0600);
+    assert_eq!(machine.peek(0x10), 0);
+}
@@ -0,0 +1,186 @@
+//! Decimal mode ADC and SBC for every accumulator, operand and carry, against the
+//! model of Bruce Clark's "Decimal Mode" tutorial (http://www.6502.org/tutorials/decimal_mode.html),
+//! appendix A for the accumulator and the carry and appendix B for N, V and Z.
+
+mod common;
+
+use b6502::{Cpu, Flags, Machine, Registers};
+
+const ADC_IMMEDIATE: u8 = 0x69;
+const SBC_IMMEDIATE: u8 = 0xE9;
+
+/// What an instruction leaves in A and in the N, V, Z and C flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Outcome {
+    acc: u8,
+    n: bool,
+    v: bool,
+    z: bool,
+    c: bool,
+}
+
+fn binary_adc(a: u8, b: u8, c: u8) -> u8 {
+    (a as u16 + b as u16 + c as u16) as u8
+}
+
+fn binary_sbc(a: u8, b: u8, c: u8) -> u8 {
+    (a as i16 - b as i16 + c as i16 - 1) as u8
+}
+
+fn expected_adc(cpu: Cpu, a: u8, b: u8, c: u8) -> Outcome {
+    let (a16, b16, c16) = (a as i16, b as i16, c as i16);
+    // seq. 1: the result and the carry
+    let mut al = (a16 & 0x0F) + (b16 & 0x0F) + c16;
+    if al >= 0x0A {
+        al = ((al + 0x06) & 0x0F) + 0x10;
+    }
+    let mut sum = (a16 & 0xF0) + (b16 & 0xF0) + al;
+    if sum >= 0xA0 {
+        sum += 0x60;
+    }
+    // seq. 2: N and V from the sum before the high nibble is adjusted
+    let signed = (a & 0xF0) as i8 as i16 + (b & 0xF0) as i8 as i16 + al;
+    let acc = sum as u8;
+    let (n, z) = match cpu {
+        Cpu::Nmos => (signed & 0x80 != 0, binary_adc(a, b, c) == 0),
+        Cpu::Cmos => (acc & 0x80 != 0, acc == 0),
+    };
+    Outcome {
+        acc,
+        n,
+        v: !(-128..=127).contains(&signed),
+        z,
+        c: sum >= 0x100,
+    }
+}
+
+fn expected_sbc(cpu: Cpu, a: u8, b: u8, c: u8) -> Outcome {
+    let (a16, b16, c16) = (a as i16, b as i16, c as i16);
+    let mut al = (a16 & 0x0F) - (b16 & 0x0F) + c16 - 1;
+    let diff = match cpu {
+        // seq. 3
+        Cpu::Nmos => {
+            if al < 0 {
+                al = ((al - 0x06) & 0x0F) - 0x10;
+            }
+            let mut diff = (a16 & 0xF0) - (b16 & 0xF0) + al;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            diff
+        }
+        // seq. 4
+        Cpu::Cmos => {
+            let mut diff = a16 - b16 + c16 - 1;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if al < 0 {
+                diff -= 0x06;
+            }
+            diff
+        }
+    };
+    let acc = diff as u8;
+    // N and Z come from the binary difference on the NMOS 6502, V and C always do
+    let binary = binary_sbc(a, b, c);
+    let shown = if cpu == Cpu::Nmos { binary } else { acc };
+    let signed = a as i8 as i16 - b as i8 as i16 + c16 - 1;
+    Outcome {
+        acc,
+        n: shown & 0x80 != 0,
+        v: !(-128..=127).contains(&signed),
+        z: shown == 0,
+        c: a16 + c16 > b16,
+    }
+}
+
+fn machine(cpu: Cpu) -> Machine {
+    Machine::builder().cpu(cpu).build(common::ram())
+}
+
+/// Run `opcode #b` with A = `a` and the carry `c` in decimal mode
+fn run(machine: &mut Machine, opcode: u8, a: u8, b: u8, c: u8) -> Outcome {
+    machine.load_jmp(0x0600, &[opcode, b]).unwrap();
+    let mut flags = Flags::DECIMAL;
+    flags.set(Flags::CARRY, c == 1);
+    machine.set_registers(Registers {
+        acc: a,
+        x: 0,
+        y: 0,
+        sp: 0xFF,
+        pc: 0x0600,
+        flags,
+    });
+    machine.step().unwrap();
+    let r = machine.registers();
+    Outcome {
+        acc: r.acc,
+        n: r.flags.contains(Flags::NEGATIVE),
+        v: r.flags.contains(Flags::OVERFLOW),
+        z: r.flags.contains(Flags::ZERO),
+        c: r.flags.contains(Flags::CARRY),
+    }
+}
+
+fn exhaustive(cpu: Cpu) {
+    let mut machine = machine(cpu);
+    let mut failures = Vec::new();
+    for a in 0..=0xFF {
+        for b in 0..=0xFF {
+            for c in 0..=1 {
+                for (name, opcode, expected) in [
+                    ("ADC", ADC_IMMEDIATE, expected_adc(cpu, a, b, c)),
+                    ("SBC", SBC_IMMEDIATE, expected_sbc(cpu, a, b, c)),
+                ] {
+                    let got = run(&mut machine, opcode, a, b, c);
+                    if got != expected {
+                        failures.push(format!(
+                            "{name} {a:0>2x} {b:0>2x} c={c}: {got:?} != {expected:?}"
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} mismatches, first {}",
+        failures.len(),
+        failures[0]
+    );
+}
+
+#[test]
+fn nmos() {
+    exhaustive(Cpu::Nmos);
+}
+
+#[test]
+fn cmos() {
+    exhaustive(Cpu::Cmos);
+}
+
+/// The reference itself on valid BCD operands, where both chips add and subtract
+/// decimal numbers
+#[test]
+fn model_on_bcd() {
+    let bcd = |n: u16| (((n / 10) << 4) | (n % 10)) as u8;
+    for cpu in [Cpu::Nmos, Cpu::Cmos] {
+        for a in 0..100 {
+            for b in 0..100 {
+                for c in 0..=1 {
+                    let sum = expected_adc(cpu, bcd(a), bcd(b), c as u8);
+                    assert_eq!(sum.acc, bcd((a + b + c) % 100));
+                    assert_eq!(sum.c, a + b + c >= 100);
+                    let diff = expected_sbc(cpu, bcd(a), bcd(b), c as u8);
+                    assert_eq!(diff.acc, bcd((a + 100 - b + c - 1) % 100));
+                    assert_eq!(diff.c, a + c > b);
+                }
+            }
+        }
+    }
+    // 99 + 1 is zero, but Z follows the binary sum $9a on the NMOS 6502
+    assert!(!expected_adc(Cpu::Nmos, 0x99, 0x01, 0).z);
+    assert!(expected_adc(Cpu::Cmos, 0x99, 0x01, 0).z);
+}
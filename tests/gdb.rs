@@ -0,0 +1,149 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use b6502::{
+    Machine, gdb,
+    watch::{Access, WatchAction, Watchpoint},
+};
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Send a packet and return the reply, checking the acks and the checksum
+    fn request(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${data}#{checksum:02x}").unwrap();
+        assert_eq!(self.byte(), b'+');
+        assert_eq!(self.byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b => reply.push(b),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let expected = reply.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{expected:02x}")
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+fn machine(program: &[u8]) -> Machine {
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, program).unwrap();
+    machine
+}
+
+/// Serve the machine here while `script` plays the client in another thread
+fn session(mut machine: Machine, script: impl FnOnce(&mut Client) + Send + 'static) -> Machine {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client { stream };
+        script(&mut client);
+    });
+    let (stream, _) = listener.accept().unwrap();
+    gdb::serve(&mut machine, stream).unwrap();
+    client.join().unwrap();
+    machine
+}
+
+// LDX #$00, 0602 INX, STX $10, CPX #$05, BNE -7, JAM
+const LOOP: [u8; 10] = [0xa2, 0x00, 0xe8, 0x86, 0x10, 0xe0, 0x05, 0xd0, 0xf9, 0x02];
+
+#[test]
+fn registers_and_memory() {
+    let machine = session(machine(&LOOP), |gdb| {
+        assert!(gdb.request("qSupported:swbreak+").contains("hwbreak+"));
+        assert_eq!(gdb.request("?"), "S05");
+        assert_eq!(gdb.request("g"), "000000ff000620");
+        assert_eq!(gdb.request("P0=42"), "OK");
+        assert_eq!(gdb.request("P4=0206"), "OK");
+        assert_eq!(gdb.request("p4"), "0206");
+        assert_eq!(gdb.request("m0600,3"), "a200e8");
+        assert_eq!(gdb.request("M0010,2:beef"), "OK");
+        assert_eq!(gdb.request("m0010,2"), "beef");
+        assert_eq!(gdb.request("G0102030405060b"), "OK");
+        assert_eq!(gdb.request("g"), "0102030405062b");
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+        assert!(
+            gdb.request("qXfer:features:read:target.xml:0,1000")
+                .starts_with("l<?xml")
+        );
+        assert_eq!(gdb.request("D"), "OK");
+    });
+    assert_eq!(machine.registers().acc, 1);
+    assert_eq!(machine.peek(0x11), 0xef);
+}
+
+#[test]
+fn stepping_and_stopping() {
+    // logging from the REPL doesn't stop the client
+    let mut logging = machine(&LOOP);
+    logging.watchpoints_mut().add(Watchpoint {
+        range: 0x10..=0x10,
+        access: Access::Write,
+        action: WatchAction::Log,
+    });
+    let machine = session(logging, |gdb| {
+        gdb.request("qSupported:swbreak+;hwbreak+");
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("p4"), "0206");
+
+        assert_eq!(gdb.request("Z0,0607,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.request("p4"), "0706");
+        assert_eq!(gdb.request("z0,0607,1"), "OK");
+
+        // the third store to $10
+        assert_eq!(gdb.request("Z2,0010,1"), "OK");
+        assert_eq!(gdb.request("Z2,0010,1"), "OK");
+        assert_eq!(gdb.request("c"), "T05watch:0010;");
+        assert_eq!(gdb.request("c"), "T05watch:0010;");
+        assert_eq!(gdb.request("m0010,1"), "03");
+        assert_eq!(gdb.request("z2,0010,1"), "OK");
+
+        assert_eq!(gdb.request("c"), "S04");
+        assert_eq!(gdb.request("m0010,1"), "05");
+        // halted on the JAM
+        assert_eq!(gdb.request("s"), "S04");
+        assert_eq!(gdb.request("p4"), "0906");
+    });
+    assert_eq!(machine.registers().x, 5);
+}
+
+#[test]
+fn errors_keep_serving() {
+    // INX, then an undocumented LAX the strict decoder rejects
+    let mut strict = Machine::builder().undocumented(false).build(common::ram());
+    strict.load_jmp(0x0600, &[0xe8, 0xa7, 0x10]).unwrap();
+    let machine = session(strict, |gdb| {
+        assert_eq!(gdb.request("c0g00"), "E01");
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("s"), "S04");
+        assert_eq!(gdb.request("p1"), "01");
+        assert_eq!(gdb.request("D"), "OK");
+    });
+    assert_eq!(machine.registers().x, 1);
+}
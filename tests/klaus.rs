@@ -0,0 +1,100 @@
+//! Klaus Dormann's 6502 functional and decimal tests.
+//!
+//! The binaries are not part of the repository, assemble them from
+//! https://github.com/Klaus2m5/6502_65C02_functional_tests (or take the prebuilt ones)
+//! and copy them into `tests/roms`, or point `B6502_ROMS` at their directory,
+//! then run `cargo test --test klaus -- --ignored`.
+
+mod common;
+
+use b6502::{Machine, Status, headless::is_trap};
+use common::{machine, rom};
+
+const FUNCTIONAL_ROM: &str = "6502_functional_test.bin";
+/// The binary is a full 64K image, the code starts at $0400
+const FUNCTIONAL_START: u16 = 0x0400;
+/// The `success` trap of the default build
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+/// Number of the test case being run
+const FUNCTIONAL_TEST_CASE: u16 = 0x0200;
+
+const DECIMAL_ROM: &str = "6502_decimal_test.bin";
+const DECIMAL_ORIGIN: u16 = 0x0200;
+/// 0 when every result matched, 1 otherwise
+const DECIMAL_ERROR: u16 = 0x000B;
+/// The default `end_of_test` of the decimal test is a 65C02 STP
+const STP: u8 = 0xDB;
+
+const MAX_CYCLES: u64 = 200_000_000;
+
+/// Run until the program traps, halts or reaches a STP, returns the stopping pc
+fn run_to_trap(machine: &mut Machine) -> u16 {
+    loop {
+        let pc = machine.registers().pc;
+        if machine.peek(pc) == STP {
+            return pc;
+        }
+        let status = machine
+            .step()
+            .unwrap_or_else(|e| panic!("error at pc {pc:0>4x}: {e}"));
+        if matches!(status, Status::Halt) || (machine.registers().pc == pc && is_trap(machine, pc))
+        {
+            return pc;
+        }
+        assert!(
+            machine.cycles() < MAX_CYCLES,
+            "no trap after {MAX_CYCLES} cycles, pc {pc:0>4x}"
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms or B6502_ROMS"]
+fn functional() {
+    let image = rom(FUNCTIONAL_ROM);
+    let mut machine = machine();
+    machine.load(0, &image).unwrap();
+    machine.load_jmp(FUNCTIONAL_START as usize, &[]).unwrap();
+    let pc = run_to_trap(&mut machine);
+    assert_eq!(
+        pc,
+        FUNCTIONAL_SUCCESS,
+        "functional test case {:0>2x} failed at pc {:0>4x}, {}",
+        machine.peek(FUNCTIONAL_TEST_CASE),
+        pc,
+        machine.registers()
+    );
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms or B6502_ROMS"]
+fn decimal() {
+    let program = rom(DECIMAL_ROM);
+    let mut machine = machine();
+    machine.load_jmp(DECIMAL_ORIGIN as usize, &program).unwrap();
+    let pc = run_to_trap(&mut machine);
+    assert_eq!(
+        machine.peek(DECIMAL_ERROR),
+        0,
+        "decimal test failed, stopped at pc {:0>4x}, {}",
+        pc,
+        machine.registers()
+    );
+}
+
+/// The harness itself: a program counting to 5 in X, then trapping on success
+#[test]
+fn trap_detection() {
+    let mut machine = machine();
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x00,       // 0600 LDX #0
+        0xE8,             // 0602 INX
+        0xE0, 0x05,       // 0603 CPX #5
+        0xD0, 0xFB,       // 0605 BNE $0602
+        0xF0, 0xFE,       // 0607 BEQ $0607 (success)
+    ];
+    machine.load_jmp(0x0600, &program).unwrap();
+    assert_eq!(run_to_trap(&mut machine), 0x0607);
+    assert_eq!(machine.registers().x, 5);
+}
@@ -0,0 +1,61 @@
+use b6502::loader::Image;
+
+const PROGRAM: [u8; 7] = [0xA9, 0x42, 0x85, 0x10, 0xA2, 0x07, 0x00];
+
+fn program_at_0600(image: &Image) {
+    assert_eq!(image.segments.len(), 1);
+    assert_eq!(image.segments[0].addr, 0x0600);
+    assert_eq!(image.segments[0].data, PROGRAM);
+}
+
+#[test]
+fn intel_hex() {
+    let image =
+        Image::intel_hex(":07060000A9428510A20700CA\n:0400000300000600F3\n:00000001FF\n").unwrap();
+    program_at_0600(&image);
+    assert_eq!(image.entry, Some(0x0600));
+}
+
+#[test]
+fn srecord() {
+    let image =
+        Image::srecord("S0080000712E7331397B\nS10A0600A9428510A20700C6\nS9030600F6\n").unwrap();
+    program_at_0600(&image);
+    assert_eq!(image.entry, Some(0x0600));
+}
+
+#[test]
+fn paper_tape() {
+    let image = Image::paper_tape(";070600A9428510A207000236\n;0000010001\n").unwrap();
+    program_at_0600(&image);
+    assert_eq!(image.entry, None);
+}
+
+#[test]
+fn errors_carry_the_line() {
+    let checksum =
+        Image::intel_hex(":0400000300000600F3\n\n:07060000A9428510A20700CB\n").unwrap_err();
+    assert_eq!(checksum.to_string(), "line 3: checksum mismatch");
+    let count = Image::srecord("S10B0600A9428510A20700C5\n").unwrap_err();
+    assert!(count.to_string().starts_with("line 1: length"));
+    let empty = Image::srecord("S\n").unwrap_err();
+    assert_eq!(empty.to_string(), "line 1: record too short");
+    let no_checksum = Image::intel_hex(":00000000").unwrap_err();
+    assert_eq!(no_checksum.to_string(), "line 1: record too short");
+    let outside = Image::intel_hex(":020000040001F9\n:01000000EA15\n").unwrap_err();
+    assert!(
+        outside
+            .to_string()
+            .contains("outside the 64K address space")
+    );
+    let last = Image::paper_tape(";070600A9428510A207000236\n;0000020002\n").unwrap_err();
+    assert!(last.to_string().starts_with("line 2:"));
+}
+
+#[test]
+fn prg() {
+    let image = Image::prg(&[0x00, 0xC0, 0xEA]).unwrap();
+    assert_eq!(image.segments[0].addr, 0xC000);
+    assert_eq!(image.entry, Some(0xC000));
+    assert!(Image::prg(&[0x00]).is_err());
+}
@@ -0,0 +1,100 @@
+//! nestest (https://www.qmtpro.com/~nes/misc/nestest.txt) in automation mode,
+//! compared line by line against its reference log. Copy `nestest.nes` and
+//! `nestest.log` into `tests/roms`, or point `B6502_ROMS` at their directory,
+//! then run `cargo test --test nestest -- --ignored`.
+
+mod common;
+
+use std::io::Cursor;
+
+use b6502::{
+    Flags, Machine, Registers,
+    trace::{Comparison, LogComparator},
+};
+use common::{machine, rom};
+
+const INES_HEADER: usize = 16;
+const PRG_BANK: usize = 0x4000;
+/// Automation mode skips the PPU driven menu
+const AUTOMATION_START: u16 = 0xC000;
+
+fn machine_at(program: &[u8]) -> Machine {
+    let mut machine = machine();
+    machine.load_jmp(0x0600, program).unwrap();
+    machine
+}
+
+/// Step until the reference log runs out, panicking at the first divergence
+fn compare(machine: &mut Machine, log: &[u8]) -> usize {
+    let mut comparator = LogComparator::new(Cursor::new(log));
+    let mut lines = 0;
+    loop {
+        match comparator.check(machine).unwrap() {
+            Comparison::Match => lines += 1,
+            Comparison::End => return lines,
+            Comparison::Diverged(d) => panic!(
+                "diverged at line {}\nexpected: {}\nactual:   {}",
+                d.line, d.expected, d.actual
+            ),
+        }
+        machine.step().unwrap();
+    }
+}
+
+#[test]
+#[ignore = "needs the ROMs in tests/roms or B6502_ROMS"]
+fn nestest() {
+    let (cartridge, log) = (rom("nestest.nes"), rom("nestest.log"));
+    let prg = &cartridge[INES_HEADER..INES_HEADER + PRG_BANK * cartridge[4] as usize];
+    let mut machine = machine();
+    // a single 16K bank is mirrored at $8000 and $C000
+    machine.load(0x8000, &prg[..PRG_BANK]).unwrap();
+    machine.load(0xC000, &prg[prg.len() - PRG_BANK..]).unwrap();
+    machine.reset().unwrap();
+    machine.set_registers(Registers {
+        acc: 0,
+        x: 0,
+        y: 0,
+        sp: 0xFD,
+        pc: AUTOMATION_START,
+        flags: Flags::INTERRUPT_DISABLE,
+    });
+    compare(&mut machine, &log);
+}
+
+/// The comparator itself, against a log written by hand in the nestest layout
+#[test]
+fn divergence() {
+    #[rustfmt::skip]
+    let program = [
+        0xA2, 0x03, // LDX #$03
+        0xCA,       // DEX
+        0xD0, 0xFD, // BNE $0602
+    ];
+    let log = "\
+0600  A2 03     LDX #$03                        A:00 X:00 Y:00 P:20 SP:FF PPU:  0, 21 CYC:0
+0602  CA        DEX                             A:00 X:03 Y:00 P:20 SP:FF PPU:  0, 27 CYC:2
+0603  D0 FD     BNE $0602                       A:00 X:02 Y:00 P:20 SP:FF PPU:  0, 33 CYC:4
+0602  CA        DEX                             A:00 X:02 Y:00 P:20 SP:FF PPU:  0, 42 CYC:7
+";
+    assert_eq!(compare(&mut machine_at(&program), log.as_bytes()), 4);
+
+    let wrong = log.replace(
+        "X:02 Y:00 P:20 SP:FF PPU:  0, 42",
+        "X:01 Y:00 P:20 SP:FF PPU:  0, 42",
+    );
+    let mut machine = machine_at(&program);
+    let mut comparator = LogComparator::new(Cursor::new(wrong.as_bytes()));
+    for _ in 0..3 {
+        assert!(matches!(
+            comparator.check(&machine).unwrap(),
+            Comparison::Match
+        ));
+        machine.step().unwrap();
+    }
+    let Comparison::Diverged(d) = comparator.check(&machine).unwrap() else {
+        panic!("expected a divergence");
+    };
+    assert_eq!(d.line, 4);
+    assert!(d.actual.starts_with("0602  CA        DEX"));
+}
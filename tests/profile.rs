@@ -0,0 +1,90 @@
+mod common;
+
+use b6502::{
+    Machine, Status,
+    debugger::{Debugger, Flow},
+    profile::{Counts, Routine},
+};
+
+/// 0600 JSR sub, JSR sub, JAM, 0608 sub: JSR inner, RTS, 060c inner: NOP, RTS
+fn machine() -> Machine {
+    let mut machine = common::machine();
+    let program = [
+        0x20, 0x08, 0x06, 0x20, 0x08, 0x06, 0x02, 0x00, 0x20, 0x0c, 0x06, 0x60, 0xea, 0x60,
+    ];
+    machine.load_jmp(0x0600, &program).unwrap();
+    machine
+        .symbols_mut()
+        .parse("sub = $0608\ninner = $060c\n")
+        .unwrap();
+    machine
+}
+
+#[test]
+fn routines_and_stacks() {
+    let mut machine = machine();
+    machine.record_profile(true);
+    while let Status::Cont = machine.step().unwrap() {}
+    let profiler = machine.profiler().unwrap();
+    // the JAM takes its cycles too
+    assert_eq!(profiler.cycles(), 54);
+    assert_eq!(
+        profiler.address(0x060c),
+        Counts {
+            executions: 2,
+            cycles: 4
+        }
+    );
+    let routines = profiler.routines();
+    assert_eq!(
+        routines[&0x0608],
+        Routine {
+            calls: 2,
+            inclusive: 40,
+            exclusive: 24
+        }
+    );
+    assert_eq!(
+        routines[&0x060c],
+        Routine {
+            calls: 2,
+            inclusive: 16,
+            exclusive: 16
+        }
+    );
+    let mut collapsed = Vec::new();
+    profiler
+        .write_collapsed(machine.symbols(), &mut collapsed)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(collapsed).unwrap(),
+        "(top level) 14\nsub 24\nsub;inner 16\n"
+    );
+}
+
+#[test]
+fn debugger_report() {
+    let mut machine = machine();
+    let mut debugger = Debugger::new();
+    let error = debugger.command(&mut machine, "profile", &mut Vec::new());
+    assert!(error.is_err());
+    let mut run = |machine: &mut Machine, line: &str| {
+        let mut out = Vec::new();
+        let flow = debugger.command(machine, line, &mut out).unwrap();
+        assert_eq!(flow, Flow::Continue);
+        String::from_utf8(out).unwrap()
+    };
+    run(&mut machine, "profile on");
+    run(&mut machine, "until 0606");
+    let report = run(&mut machine, "profile");
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines[0], "52 cycles, 10 instructions");
+    assert!(lines[3].ends_with("(top level)"));
+    assert_eq!(
+        lines[4],
+        "       2         40  76.9%         24  46.2%  sub"
+    );
+    assert!(report.contains("060c          2          4   7.7%  inner    NOP"));
+    run(&mut machine, "profile off");
+    assert!(machine.profiler().is_none());
+}
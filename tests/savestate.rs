@@ -0,0 +1,73 @@
+mod common;
+
+use std::io::Cursor;
+
+use b6502::{
+    Machine, MemoryMap, Registers,
+    bus::{ADDRESS_SPACE, Framebuffer, Keyboard, Ram},
+    savestate,
+};
+
+fn machine() -> Machine {
+    let mut bus = MemoryMap::new();
+    bus.map(0x0000..=0xFFFF, Ram::new(ADDRESS_SPACE))
+        .map(0x00FF..=0x00FF, Keyboard::default())
+        .map(0x0200..=0x05FF, Framebuffer::default());
+    Machine::builder().build(bus)
+}
+
+#[test]
+fn round_trip() {
+    // LDA $ff, STA $0200, INX, JMP $0600
+    let program = [0xa5, 0xff, 0x8d, 0x00, 0x02, 0xe8, 0x4c, 0x00, 0x06];
+    let mut machine = machine();
+    machine.load_jmp(0x0600, &program).unwrap();
+    machine
+        .bus_mut()
+        .device_mut::<Keyboard>()
+        .unwrap()
+        .press(0x77);
+    machine.run_for_cycles(100).unwrap();
+
+    let mut state = Vec::new();
+    savestate::save(&machine, &mut state).unwrap();
+    assert!(state.starts_with(savestate::MAGIC));
+
+    let mut restored = self::machine();
+    savestate::load(&mut restored, &mut Cursor::new(&state)).unwrap();
+    assert_eq!(restored.registers(), machine.registers());
+    assert_eq!(restored.cycles(), machine.cycles());
+    assert_eq!(restored.peek(0x00ff), 0x77);
+    assert_eq!(restored.peek(0x0200), 0x77);
+    assert_eq!(restored.peek(0x0601), 0xff);
+
+    // both machines carry on identically
+    machine.run_for_cycles(50).unwrap();
+    restored.run_for_cycles(50).unwrap();
+    assert_eq!(restored.registers(), machine.registers());
+}
+
+#[test]
+fn rejects_other_machines() {
+    let mut machine = machine();
+    machine.set_registers(Registers {
+        pc: 0x1234,
+        ..machine.registers()
+    });
+    let mut state = Vec::new();
+    savestate::save(&machine, &mut state).unwrap();
+
+    let mut flat = common::machine();
+    let err = savestate::load(&mut flat, &mut Cursor::new(&state)).unwrap_err();
+    assert!(format!("{err:#}").contains("memory map"), "{err:#}");
+
+    let mut cmos = Machine::builder()
+        .cpu(b6502::Cpu::Cmos)
+        .build(MemoryMap::new());
+    assert!(savestate::load(&mut cmos, &mut Cursor::new(&state)).is_err());
+
+    state[9] = 99;
+    let err = savestate::load(&mut self::machine(), &mut Cursor::new(&state)).unwrap_err();
+    assert!(err.to_string().contains("version"), "{err}");
+    assert!(savestate::load(&mut self::machine(), &mut Cursor::new(&state[..20])).is_err());
+}
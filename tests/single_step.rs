@@ -0,0 +1,216 @@
+//! Per-opcode vectors in the SingleStepTests format
+//! (https://github.com/SingleStepTests/65x02): one JSON file per opcode,
+//! each holding thousands of initial state, final state and bus cycle triples.
+//!
+//! The runner looks for `6502/v1/*.json` (NMOS) and `wdc65c02/v1/*.json` (65C02)
+//! under `tests/single_step`, or under the directory named by `B6502_SINGLE_STEP`,
+//! with `cargo test --test single_step -- --ignored`.
+//! Bus cycle sequences are only compared with `B6502_BUS_CYCLES=1`: the core does
+//! not perform the dummy reads and writes of the real chip, only their cycle count.
+
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use b6502::{Bus, Cpu, Flags, Machine, Registers, bus::ADDRESS_SPACE};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// B and bit 5 are not stored in the register
+const P_MASK: u8 = !(Flags::BREAK.bits() | Flags::UNUSED.bits());
+
+/// The full 64K of RAM, recording every CPU access
+struct RecordingBus {
+    ram: Vec<u8>,
+    accesses: Vec<(u16, u8, String)>,
+}
+
+impl Bus for RecordingBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.ram[addr as usize];
+        self.accesses.push((addr, value, "read".into()));
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize] = value;
+        self.accesses.push((addr, value, "write".into()));
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.ram[addr as usize]
+    }
+
+    fn poke(&mut self, addr: u16, value: u8) {
+        self.ram[addr as usize] = value;
+    }
+}
+
+fn machine(cpu: Cpu, state: &State) -> Machine<RecordingBus> {
+    let bus = RecordingBus {
+        ram: vec![0; ADDRESS_SPACE],
+        accesses: Vec::new(),
+    };
+    let mut machine = Machine::builder().cpu(cpu).build(bus);
+    machine.set_registers(Registers {
+        acc: state.a,
+        x: state.x,
+        y: state.y,
+        sp: state.s,
+        pc: state.pc,
+        flags: Flags::from_bits_retain(state.p),
+    });
+    for &(addr, value) in &state.ram {
+        machine.poke(addr, value);
+    }
+    machine
+}
+
+/// Step one instruction from the initial state, returns what differs from the final state
+fn check(cpu: Cpu, case: &Case, bus_cycles: bool) -> Option<String> {
+    let mut machine = machine(cpu, &case.initial);
+    if let Err(e) = machine.step() {
+        return Some(format!("{}: {e}", case.name));
+    }
+    let expected = &case.expected;
+    let mut diffs = Vec::new();
+    let r = machine.registers();
+    for (name, got, want) in [
+        ("pc", r.pc, expected.pc),
+        ("s", r.sp as u16, expected.s as u16),
+        ("a", r.acc as u16, expected.a as u16),
+        ("x", r.x as u16, expected.x as u16),
+        ("y", r.y as u16, expected.y as u16),
+        (
+            "p",
+            (r.flags.bits() & P_MASK) as u16,
+            (expected.p & P_MASK) as u16,
+        ),
+    ] {
+        if got != want {
+            diffs.push(format!("{name} {got:x} != {want:x}"));
+        }
+    }
+    for &(addr, want) in &expected.ram {
+        let got = machine.peek(addr);
+        if got != want {
+            diffs.push(format!("[{addr:0>4x}] {got:0>2x} != {want:0>2x}"));
+        }
+    }
+    if machine.cycles() != case.cycles.len() as u64 {
+        diffs.push(format!(
+            "cycles {} != {}",
+            machine.cycles(),
+            case.cycles.len()
+        ));
+    }
+    if bus_cycles && machine.bus().accesses != case.cycles {
+        diffs.push(format!(
+            "bus {:?} != {:?}",
+            machine.bus().accesses,
+            case.cycles
+        ));
+    }
+    (!diffs.is_empty()).then(|| format!("{}: {}", case.name, diffs.join(", ")))
+}
+
+#[derive(Default)]
+struct Summary {
+    total: usize,
+    failed: usize,
+    first: Option<String>,
+}
+
+fn run(cpu: Cpu, dir: &str) {
+    let root = env::var_os("B6502_SINGLE_STEP")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/single_step"));
+    let dir = root.join(dir);
+    let entries = fs::read_dir(&dir).unwrap_or_else(|e| panic!("{}: {e}", dir.display()));
+    let bus_cycles = env::var_os("B6502_BUS_CYCLES").is_some();
+    let mut summaries: BTreeMap<u8, Summary> = BTreeMap::new();
+    for entry in entries {
+        let path = entry.unwrap().path();
+        let Some(opcode) = path
+            .file_stem()
+            .and_then(|stem| u8::from_str_radix(&stem.to_string_lossy(), 16).ok())
+        else {
+            continue;
+        };
+        let cases: Vec<Case> = serde_json::from_slice(&fs::read(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        let summary = summaries.entry(opcode).or_default();
+        for case in &cases {
+            summary.total += 1;
+            if let Some(failure) = check(cpu, case, bus_cycles) {
+                summary.failed += 1;
+                summary.first.get_or_insert(failure);
+            }
+        }
+    }
+    assert!(!summaries.is_empty(), "no vectors in {}", dir.display());
+    let failing: Vec<_> = summaries
+        .iter()
+        .filter(|(_, summary)| summary.failed > 0)
+        .collect();
+    for (opcode, summary) in &failing {
+        eprintln!(
+            "{opcode:0>2x}: {}/{} failed, first {}",
+            summary.failed,
+            summary.total,
+            summary.first.as_deref().unwrap_or_default()
+        );
+    }
+    assert!(
+        failing.is_empty(),
+        "{} of {} opcodes failed",
+        failing.len(),
+        summaries.len()
+    );
+}
+
+#[test]
+#[ignore = "needs the vectors in tests/single_step or B6502_SINGLE_STEP"]
+fn nmos() {
+    run(Cpu::Nmos, "6502/v1");
+}
+
+#[test]
+#[ignore = "needs the vectors in tests/single_step or B6502_SINGLE_STEP"]
+fn cmos() {
+    run(Cpu::Cmos, "wdc65c02/v1");
+}
+
+/// The harness itself, on a vector written in the same format
+#[test]
+fn lda_immediate() {
+    let case: Case = serde_json::from_str(
+        r#"{
+            "name": "a9 80 ea",
+            "initial": {"pc": 1024, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                        "ram": [[1024, 169], [1025, 128], [1026, 234]]},
+            "final": {"pc": 1026, "s": 253, "a": 128, "x": 0, "y": 0, "p": 164,
+                      "ram": [[1024, 169], [1025, 128], [1026, 234]]},
+            "cycles": [[1024, 169, "read"], [1025, 128, "read"]]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(check(Cpu::Nmos, &case, true), None);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use b6502::{
+    Cpu,
+    debugger::{Debugger, Flow},
+    expr::Expr,
+    parse_opcode,
+    symbols::Symbols,
+};
+
+const DBG: &str = "\
+version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=12,mod=1,scope=2,seg=3,span=10,sym=3,type=4
+sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,ref=2,val=0x600,seg=0,type=lab
+sym\tid=1,name=\"KEY_UP\",addrsize=zeropage,scope=0,def=3,val=0x77,type=equ
+sym\tid=2,name=\"counter\",addrsize=zeropage,size=1,scope=0,def=4,val=0x10,seg=1,type=lab
+";
+
+#[test]
+fn formats() {
+    let mut symbols = Symbols::new();
+    assert_eq!(symbols.parse(DBG).unwrap(), 2);
+    assert_eq!(symbols.get("main"), Some(0x0600));
+    assert_eq!(symbols.get("KEY_UP"), None);
+
+    let vice = "al C:0607 .sub\nal 0609 .tail\n";
+    assert_eq!(symbols.parse(vice).unwrap(), 2);
+    assert_eq!(symbols.name(0x0607), Some("sub"));
+
+    let plain = "; the screen\nscreen = $0200\nvectors=0xfffa\nlimit = 32 # decimal\n";
+    assert_eq!(symbols.parse(plain).unwrap(), 3);
+    assert_eq!(symbols.get("limit"), Some(32));
+    assert!(symbols.parse("what is this").is_err());
+
+    assert_eq!(symbols.label(0x0603).as_deref(), Some("main+3"));
+    assert_eq!(symbols.label(0x0400), None);
+    assert_eq!(symbols.resolve("sub+2").unwrap(), 0x0609);
+    assert_eq!(symbols.resolve("$beef").unwrap(), 0xbeef);
+    assert_eq!(symbols.resolve("c0de").unwrap(), 0xc0de);
+    assert!(symbols.resolve("nowhere").is_err());
+
+    let op = |bytes: &[u8]| {
+        parse_opcode(&mut bytes.iter().copied(), Cpu::Nmos, true)
+            .unwrap()
+            .unwrap()
+    };
+    assert_eq!(symbols.operation(&op(&[0xb5, 0x10]), 0), "LDA counter,X");
+    assert_eq!(symbols.operation(&op(&[0xd0, 0xfe]), 0x0609), "BNE tail");
+    assert_eq!(symbols.operation(&op(&[0xa9, 0x10]), 0), "LDA #$10");
+    assert_eq!(
+        symbols.operation(&op(&[0x6c, 0xfc, 0xff]), 0),
+        "JMP (vectors+2)"
+    );
+}
+
+// 0600 JSR $0607, LDX #$01, JAM, 0607 LDA #$42, INY, RTS
+const PROGRAM: [u8; 11] = [
+    0x20, 0x07, 0x06, 0xa2, 0x01, 0x02, 0x00, 0xa9, 0x42, 0xc8, 0x60,
+];
+
+#[test]
+fn debugger_names() {
+    let mut machine = common::machine();
+    machine.load_jmp(0x0600, &PROGRAM).unwrap();
+    machine
+        .symbols_mut()
+        .parse("main = $0600\nsub = $0607\n")
+        .unwrap();
+    let mut debugger = Debugger::new();
+    let mut run = |line: &str| {
+        let mut out = Vec::new();
+        let flow = debugger.command(&mut machine, line, &mut out).unwrap();
+        assert_eq!(flow, Flow::Continue);
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!(
+        run("dis main 2"),
+        "main:\n> 0600  20 07 06  JSR sub\n  0603  a2 01     LDX #$01\n"
+    );
+    run("break sub+2");
+    assert!(run("continue").contains("> 0609  c8        INY"));
+    assert_eq!(run("x sub+1 2"), "0608: 42 c8 \n");
+    run("delete 0");
+    assert_eq!(run("x main 1"), "main:\n0600: 20 \n");
+    let expr: Expr = "pc == sub + 2".parse().unwrap();
+    assert!(expr.holds(&machine).unwrap());
+    assert!("nowhere".parse::<Expr>().unwrap().eval(&machine).is_err());
+}